@@ -0,0 +1,23 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use sleipnir::libs::graphite::{GraphiteMetric, scalar};
+use std::hint::black_box;
+
+const UNTAGGED: &str = "servers.dc1.web01.cpu.user 42.5 1234567890";
+const TAGGED: &str = "app.http.requests;host=web01.example.com;dc=eu-west-1;env=production;\
+                      method=GET;status=200;route=/api/v1/users 1502.25 1234567890";
+
+fn bench_parse(c: &mut Criterion) {
+    for (name, line) in [("untagged", UNTAGGED), ("tagged", TAGGED)] {
+        let mut group = c.benchmark_group(name);
+        group.bench_function("simd", |b| {
+            b.iter(|| GraphiteMetric::parse(black_box(line)).unwrap())
+        });
+        group.bench_function("scalar", |b| {
+            b.iter(|| scalar::parse(black_box(line)).unwrap())
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,81 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sleipnir::libs::cache::Cache;
+use sleipnir::libs::filter;
+use sleipnir::libs::graphite::GraphiteMetric;
+use sleipnir::libs::obf::{self, Options, Strategy};
+use std::hint::black_box;
+
+const SERIES: usize = 10_000;
+const POINTS: usize = 100_000;
+
+// tagged series, popularity of series follows Zipf's law
+fn lines() -> Vec<String> {
+    let series: Vec<String> = (0..SERIES)
+        .map(|i| {
+            format!(
+                "app.http.requests;host=web{:04}.example.com;dc=dc{};route=/api/v1/r{} 1.0 1234567890",
+                i % 500,
+                i % 7,
+                i
+            )
+        })
+        .collect();
+
+    let cumulative: Vec<f64> = (1..=SERIES)
+        .scan(0.0, |sum, k| {
+            *sum += 1.0 / k as f64;
+            Some(*sum)
+        })
+        .collect();
+    let total = cumulative[SERIES - 1];
+
+    let mut rng = StdRng::seed_from_u64(42);
+    (0..POINTS)
+        .map(|_| {
+            let u = rng.random::<f64>() * total;
+            series[cumulative.partition_point(|&c| c < u).min(SERIES - 1)].clone()
+        })
+        .collect()
+}
+
+fn bench_obfuscate(c: &mut Criterion) {
+    let lines = lines();
+    let metrics: Vec<GraphiteMetric> = lines
+        .iter()
+        .map(|line| GraphiteMetric::parse(line).unwrap())
+        .collect();
+    let options = Options {
+        strategy: Strategy::keyed(b"secret", 1),
+        ..Options::default()
+    };
+
+    let mut group = c.benchmark_group("obfuscate");
+    group.bench_function("direct", |b| {
+        b.iter(|| {
+            for metric in &metrics {
+                black_box(obf::obfuscate(black_box(metric), &options).unwrap());
+            }
+        })
+    });
+    for capacity in [SERIES / 10, SERIES] {
+        let cache = Cache::new(capacity);
+        group.bench_function(format!("cached {}", capacity), |b| {
+            b.iter(|| {
+                for metric in &metrics {
+                    let path = filter::path(black_box(metric));
+                    black_box(
+                        cache
+                            .get_or_insert_with(&path, || obf::obfuscate(metric, &options))
+                            .unwrap(),
+                    );
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_obfuscate);
+criterion_main!(benches);
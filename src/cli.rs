@@ -0,0 +1,155 @@
+use sleipnir::libs::config;
+use sleipnir::libs::graphite::GraphiteMetric;
+use sleipnir::libs::obf::{self, Obfuscator, Routes};
+use sleipnir::libs::rewrite::Rewriter;
+
+use std::io::{BufRead, Write};
+
+// lines from arguments, or from stdin if no arguments provided
+fn input(args: &[String]) -> Vec<String> {
+    if !args.is_empty() {
+        return args.to_vec();
+    }
+
+    std::io::stdin()
+        .lock()
+        .lines()
+        .map_while(Result::ok)
+        .collect()
+}
+
+/*
+Test rewrite rules offline:
+
+    sleipnir rewrite <rules-file> [LINE...]
+
+Lines are graphite lines or bare metric names, one rewritten
+name and the list of matched rules are printed for each line.
+*/
+pub fn rewrite(args: &[String]) -> i32 {
+    let Some(path) = args.first() else {
+        eprintln!("usage: sleipnir rewrite <rules-file> [LINE...]");
+        return 2;
+    };
+
+    let rewriter = match Rewriter::load(path) {
+        Ok(rewriter) => rewriter,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    for line in input(&args[1..]) {
+        let name = match GraphiteMetric::parse(&line) {
+            Ok(metric) => metric.name,
+            Err(_) => line.trim(),
+        };
+
+        let mut matched = Vec::new();
+        let rewritten = rewriter.apply(name, |rule| matched.push(rule.pattern.clone()));
+
+        println!("{} -> {}", name, rewritten);
+        for pattern in matched {
+            println!("    matched: {}", pattern);
+        }
+    }
+
+    0
+}
+
+/*
+Print obfuscated paths under the current configuration and key:
+
+    sleipnir obfuscate [LINE...]
+
+Lines are graphite lines or bare paths, key, options and routes are
+taken from obfuscation configuration (`APP_OBF_*`), one path is printed
+for each line.
+*/
+pub fn obfuscate(args: &[String]) -> i32 {
+    let config = config::load_obf();
+    let routes = obf::Options::load(&config).and_then(|options| match &config.obf_routes {
+        Some(path) => Routes::load(path, &options),
+        None => Ok(Routes::new(Box::new(options))),
+    });
+    let routes = match routes {
+        Ok(routes) => routes,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    write_obfuscated(&input(args), &routes, &mut std::io::stdout().lock())
+}
+
+// write obfuscated path of each line, errors go to stderr, returns exit code
+fn write_obfuscated(lines: &[String], obfuscator: &dyn Obfuscator, out: &mut impl Write) -> i32 {
+    let mut code = 0;
+    for line in lines {
+        // bare paths are completed into graphite lines
+        let completed;
+        let metric = match GraphiteMetric::parse(line) {
+            Ok(metric) => Ok(metric),
+            Err(_) => {
+                completed = format!("{} 0 0", line.trim());
+                GraphiteMetric::parse(&completed)
+            }
+        };
+
+        match metric.and_then(|m| obfuscator.obfuscate(&m).map_err(|e| e.to_string())) {
+            Ok(path) => {
+                let _ = writeln!(out, "{}", path);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", line.trim(), e);
+                code = 1;
+            }
+        }
+    }
+
+    code
+}
+
+/*
+Reverse obfuscated paths produced in encrypt mode:
+
+    sleipnir decrypt [PATH...]
+
+Key is taken from obfuscation configuration (`APP_OBF_*`), paths of
+`encrypted` routes are reversed in hash mode too, paths could be
+graphite lines too, only the path is printed back.
+*/
+pub fn decrypt(args: &[String]) -> i32 {
+    let options = match obf::Options::load(&config::load_obf()) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    // encrypted tokens of `encrypted` routes could be reversed in hash mode too
+    let Some(strategy) = &options.strategies.encrypted else {
+        eprintln!("decrypt requires a key");
+        return 1;
+    };
+
+    let mut code = 0;
+    for line in input(args) {
+        let path = line.split_whitespace().next().unwrap_or_default();
+
+        match obf::decrypt(path, strategy) {
+            Ok(clear) => println!("{}", clear),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                code = 1;
+            }
+        }
+    }
+
+    code
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,44 @@
+use super::*;
+
+fn run(lines: &[&str], obfuscator: &dyn Obfuscator) -> (i32, Vec<String>) {
+    let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    let mut out = Vec::new();
+    let code = write_obfuscated(&lines, obfuscator, &mut out);
+
+    let out = String::from_utf8(out).unwrap();
+    (code, out.lines().map(str::to_string).collect())
+}
+
+#[test]
+fn test_cli_obfuscate_bare_path() {
+    let options = obf::Options::default();
+    let metric = GraphiteMetric::parse("cpu.usage;host=server01 1 1").unwrap();
+    let expected = options.obfuscate(&metric).unwrap();
+
+    let (code, out) = run(
+        &["cpu.usage;host=server01", "cpu.usage;host=server01 1 1"],
+        &options,
+    );
+    assert_eq!(code, 0);
+    assert_eq!(out, vec![expected.clone(), expected]);
+}
+
+#[test]
+fn test_cli_obfuscate_routes() {
+    let options = obf::Options::default();
+    let routes = Routes::parse("glob:public.* passthrough", &options).unwrap();
+
+    let (code, out) = run(&["public.requests;dc=eu"], &routes);
+    assert_eq!(code, 0);
+    assert_eq!(out, vec!["public.requests;dc=eu"]);
+}
+
+#[test]
+fn test_cli_obfuscate_error() {
+    let options = obf::Options::default();
+
+    // invalid lines are skipped, the rest is still printed
+    let (code, out) = run(&["not a path", "cpu.usage"], &options);
+    assert_eq!(code, 1);
+    assert_eq!(out.len(), 1);
+}
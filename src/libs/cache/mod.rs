@@ -0,0 +1,77 @@
+// Bounded LRU cache of obfuscated paths of hot series, keyed by the
+// cleartext path seen by the obfuscator. Every obfuscation key has its
+// own cache, so tokens of different key versions are never mixed.
+//
+// Paths are obfuscated again only on misses, so tokens are reported
+// (mapping table, collision detector) on misses only, paths with
+// unreported tokens have to be removed.
+use ahash::RandomState;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+const SHARDS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Hit,
+    Miss,
+    // miss which evicted the least recently used path
+    Evicted,
+}
+
+pub struct Cache {
+    state: RandomState,
+    shards: Vec<Mutex<LruCache<Box<str>, Box<str>>>>,
+}
+
+impl Cache {
+    // keep up to `capacity` paths
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity.div_ceil(SHARDS)).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            state: RandomState::new(),
+            shards: (0..SHARDS)
+                .map(|_| Mutex::new(LruCache::new(capacity)))
+                .collect(),
+        }
+    }
+
+    fn shard(&self, path: &str) -> &Mutex<LruCache<Box<str>, Box<str>>> {
+        &self.shards[self.state.hash_one(path) as usize % SHARDS]
+    }
+
+    // cached obfuscated path, or obfuscate and remember it, errors are
+    // never cached
+    pub fn get_or_insert_with<F, E>(&self, path: &str, obfuscate: F) -> Result<(String, Outcome), E>
+    where
+        F: FnOnce() -> Result<String, E>,
+    {
+        let shard = self.shard(path);
+        if let Some(obfuscated) = shard.lock().unwrap().get(path) {
+            return Ok((obfuscated.to_string(), Outcome::Hit));
+        }
+
+        // obfuscate without lock, concurrent misses of one path are fine
+        let obfuscated = obfuscate()?;
+        let evicted = shard
+            .lock()
+            .unwrap()
+            .push(path.into(), obfuscated.as_str().into())
+            .is_some_and(|(key, _)| *key != *path);
+
+        let outcome = match evicted {
+            true => Outcome::Evicted,
+            false => Outcome::Miss,
+        };
+        Ok((obfuscated, outcome))
+    }
+
+    // forget cached path, it's obfuscated again on the next point
+    pub fn remove(&self, path: &str) {
+        self.shard(path).lock().unwrap().pop(path);
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,72 @@
+use super::*;
+
+fn obfuscate(path: &str) -> Result<String, String> {
+    Ok(format!("obf_{}", path.len()))
+}
+
+#[test]
+fn test_cache_hit_and_miss() {
+    let cache = Cache::new(100);
+
+    assert_eq!(
+        cache.get_or_insert_with("cpu.usage", || obfuscate("cpu.usage")),
+        Ok(("obf_9".to_string(), Outcome::Miss))
+    );
+    assert_eq!(
+        cache.get_or_insert_with("cpu.usage", || Err("not called".to_string())),
+        Ok(("obf_9".to_string(), Outcome::Hit))
+    );
+}
+
+#[test]
+fn test_cache_errors_not_cached() {
+    let cache = Cache::new(100);
+
+    assert!(
+        cache
+            .get_or_insert_with("cpu.usage", || Err::<String, _>("rejected"))
+            .is_err()
+    );
+    assert_eq!(
+        cache
+            .get_or_insert_with("cpu.usage", || obfuscate("cpu.usage"))
+            .map(|(_, outcome)| outcome),
+        Ok(Outcome::Miss)
+    );
+}
+
+#[test]
+fn test_cache_evictions() {
+    let cache = Cache::new(SHARDS * 10);
+
+    let evicted = (0..10_000)
+        .map(|i| format!("servers.web{}.cpu", i))
+        .filter(|path| {
+            let (_, outcome) = cache.get_or_insert_with(path, || obfuscate(path)).unwrap();
+            outcome == Outcome::Evicted
+        })
+        .count();
+
+    assert!(evicted >= 10_000 - SHARDS * 10);
+    for shard in &cache.shards {
+        assert!(shard.lock().unwrap().len() <= 10);
+    }
+}
+
+#[test]
+fn test_cache_remove() {
+    let cache = Cache::new(100);
+
+    cache
+        .get_or_insert_with("cpu.usage", || obfuscate("cpu.usage"))
+        .unwrap();
+    cache.remove("cpu.usage");
+    cache.remove("cpu.idle");
+
+    assert_eq!(
+        cache
+            .get_or_insert_with("cpu.usage", || obfuscate("cpu.usage"))
+            .map(|(_, outcome)| outcome),
+        Ok(Outcome::Miss)
+    );
+}
@@ -1,5 +1,6 @@
 use clickhouse::{Client, Row, inserter::Inserter};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Row)]
@@ -9,8 +10,181 @@ pub struct Metric {
     pub timestamp: i64,
 }
 
+impl Metric {
+    // columns and types written by the inserter
+    pub const COLUMNS: &[(&str, &str)] = &[
+        ("path", "String"),
+        ("value", "Float64"),
+        ("timestamp", "Int64"),
+    ];
+}
+
+// obfuscation mapping, written once per newly seen token
+#[derive(Debug, Clone, Serialize, Deserialize, Row)]
+pub struct Mapping {
+    pub token: String,
+    pub cleartext: String,
+    pub timestamp: i64,
+}
+
+impl Mapping {
+    // columns and types written by the mapping inserter
+    pub const COLUMNS: &[(&str, &str)] = &[
+        ("token", "String"),
+        ("cleartext", "String"),
+        ("timestamp", "Int64"),
+    ];
+}
+
+/*
+Table schema for startup bootstrap, rendered into
+
+    CREATE DATABASE IF NOT EXISTS db [ON CLUSTER c]
+    CREATE TABLE IF NOT EXISTS db.table [ON CLUSTER c] (columns)
+    ENGINE = engine [PARTITION BY ...] ORDER BY ... [TTL ...]
+
+clauses are taken as is, so anything clickhouse accepts works.
+*/
+#[derive(Debug, Clone)]
+pub struct Schema {
+    pub columns: String,
+    pub engine: String,
+    pub partition_by: Option<String>,
+    pub order_by: String,
+    pub ttl: Option<String>,
+    pub cluster: Option<String>,
+}
+
+impl Schema {
+    // layout of `Metric` rows, as in tests/clickhouse/init/init.sql
+    pub fn metrics() -> Self {
+        Self {
+            columns: "path String, \
+                      value Float64, \
+                      timestamp Int64, \
+                      time DateTime DEFAULT toDateTime(timestamp), \
+                      date Date DEFAULT toDate(time)"
+                .to_string(),
+            engine: "MergeTree".to_string(),
+            partition_by: Some("toYYYYMMDD(date)".to_string()),
+            order_by: "(path, date, timestamp)".to_string(),
+            ttl: Some("date + INTERVAL 90 DAY".to_string()),
+            cluster: None,
+        }
+    }
+
+    // layout of `Mapping` rows, one row per token after merges
+    pub fn mapping() -> Self {
+        Self {
+            columns: "token String, cleartext String, timestamp Int64".to_string(),
+            engine: "ReplacingMergeTree".to_string(),
+            partition_by: None,
+            order_by: "token".to_string(),
+            ttl: None,
+            cluster: None,
+        }
+    }
+
+    fn on_cluster(&self) -> String {
+        self.cluster
+            .as_ref()
+            .map(|cluster| format!(" ON CLUSTER `{}`", cluster))
+            .unwrap_or_default()
+    }
+
+    pub fn create_database(&self, database: &str) -> String {
+        format!(
+            "CREATE DATABASE IF NOT EXISTS `{}`{}",
+            database,
+            self.on_cluster()
+        )
+    }
+
+    pub fn create_table(&self, database: &str, table: &str) -> String {
+        let mut ddl = format!(
+            "CREATE TABLE IF NOT EXISTS `{}`.`{}`{} ({}) ENGINE = {}",
+            database,
+            table,
+            self.on_cluster(),
+            self.columns,
+            self.engine
+        );
+        if let Some(partition_by) = &self.partition_by {
+            ddl.push_str(&format!(" PARTITION BY {}", partition_by));
+        }
+        ddl.push_str(&format!(" ORDER BY {}", self.order_by));
+        if let Some(ttl) = &self.ttl {
+            ddl.push_str(&format!(" TTL {}", ttl));
+        }
+        ddl
+    }
+}
+
+// table column, as described by clickhouse
+#[derive(Debug, Clone, Deserialize, Row)]
+pub struct Column {
+    pub name: String,
+    pub column_type: String,
+    // empty, DEFAULT, MATERIALIZED, ALIAS or EPHEMERAL
+    pub default_type: String,
+}
+
+// differences between written columns and table ones, columns which
+// are not written are fine, they get default values
+pub fn diff(expected: &[(&str, &str)], columns: &[Column]) -> Vec<String> {
+    let mut diff = Vec::new();
+
+    for (name, expected_type) in expected {
+        let Some(column) = columns.iter().find(|c| c.name == *name) else {
+            diff.push(format!("missing column `{}` {}", name, expected_type));
+            continue;
+        };
+
+        let low_cardinality = format!("LowCardinality({})", expected_type);
+        if column.column_type != *expected_type && column.column_type != low_cardinality {
+            diff.push(format!(
+                "column `{}` is {}, expected {}",
+                name, column.column_type, expected_type
+            ));
+        }
+        if matches!(
+            column.default_type.as_str(),
+            "MATERIALIZED" | "ALIAS" | "EPHEMERAL"
+        ) {
+            diff.push(format!(
+                "column `{}` is {}, it can't be written",
+                name, column.default_type
+            ));
+        }
+    }
+
+    diff
+}
+
+// schema validation failure, tables which can't be described may be
+// just unreachable yet, mismatching ones won't accept inserts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaError {
+    Describe { table: String, error: String },
+    Mismatch { table: String, diff: Vec<String> },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Describe { table, error } => {
+                write!(f, "unable to describe {}: {}", table, error)
+            }
+            SchemaError::Mismatch { table, diff } => {
+                write!(f, "{} schema mismatch: {}", table, diff.join("; "))
+            }
+        }
+    }
+}
+
 pub struct ClickHouseWriter {
     client: Client,
+    database: String,
     table_name: String,
 }
 
@@ -30,10 +204,51 @@ impl ClickHouseWriter {
 
         Self {
             client,
+            database: database.to_string(),
             table_name: table_name.to_string(),
         }
     }
 
+    // create database and table if they don't exist yet
+    pub async fn bootstrap(&self, schema: &Schema) -> Result<(), clickhouse::error::Error> {
+        // database may not exist yet, so it can't be the session one
+        self.client
+            .clone()
+            .with_database("default")
+            .query(&schema.create_database(&self.database))
+            .execute()
+            .await?;
+
+        self.client
+            .query(&schema.create_table(&self.database, &self.table_name))
+            .execute()
+            .await
+    }
+
+    // table columns, DESCRIBE TABLE
+    pub async fn describe(&self) -> Result<Vec<Column>, clickhouse::error::Error> {
+        let query = format!(
+            "SELECT name, type AS column_type, default_type \
+             FROM (DESCRIBE TABLE `{}`.`{}`)",
+            self.database, self.table_name
+        );
+        self.client.query(&query).fetch_all::<Column>().await
+    }
+
+    // compare table columns with the expected ones, see `diff`
+    pub async fn validate(&self, expected: &[(&str, &str)]) -> Result<(), SchemaError> {
+        let table = format!("{}.{}", self.database, self.table_name);
+        let columns = self.describe().await.map_err(|e| SchemaError::Describe {
+            table: table.clone(),
+            error: e.to_string(),
+        })?;
+
+        match diff(expected, &columns) {
+            diff if diff.is_empty() => Ok(()),
+            diff => Err(SchemaError::Mismatch { table, diff }),
+        }
+    }
+
     #[allow(dead_code)]
     pub async fn batch(&self, metrics: Vec<Metric>) -> Result<(), clickhouse::error::Error> {
         let mut insert = self.client.insert::<Metric>(&self.table_name).await?;
@@ -46,10 +261,13 @@ impl ClickHouseWriter {
         Ok(())
     }
 
-    pub fn create_inserter(&self, max_rows: u64, period_secs: u64) -> Inserter<Metric> {
+    pub fn create_inserter<T: Row>(&self, max_rows: u64, period_secs: u64) -> Inserter<T> {
         self.client
-            .inserter::<Metric>(&self.table_name)
+            .inserter::<T>(&self.table_name)
             .with_max_rows(max_rows)
             .with_period(Some(Duration::from_secs(period_secs)))
     }
 }
+
+#[cfg(test)]
+mod tests;
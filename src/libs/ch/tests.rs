@@ -0,0 +1,115 @@
+use super::*;
+
+#[test]
+fn test_ch_schema_metrics() {
+    let schema = Schema::metrics();
+
+    assert_eq!(
+        schema.create_database("sleipnir"),
+        "CREATE DATABASE IF NOT EXISTS `sleipnir`"
+    );
+    assert_eq!(
+        schema.create_table("sleipnir", "metrics"),
+        "CREATE TABLE IF NOT EXISTS `sleipnir`.`metrics` (\
+         path String, value Float64, timestamp Int64, \
+         time DateTime DEFAULT toDateTime(timestamp), date Date DEFAULT toDate(time)) \
+         ENGINE = MergeTree PARTITION BY toYYYYMMDD(date) ORDER BY (path, date, timestamp) \
+         TTL date + INTERVAL 90 DAY"
+    );
+}
+
+#[test]
+fn test_ch_schema_clauses() {
+    let schema = Schema {
+        engine: "ReplicatedMergeTree".to_string(),
+        partition_by: None,
+        ttl: Some("date + INTERVAL 90 DAY".to_string()),
+        cluster: Some("main".to_string()),
+        ..Schema::metrics()
+    };
+
+    assert_eq!(
+        schema.create_database("sleipnir"),
+        "CREATE DATABASE IF NOT EXISTS `sleipnir` ON CLUSTER `main`"
+    );
+
+    let ddl = schema.create_table("sleipnir", "metrics");
+    assert!(ddl.starts_with("CREATE TABLE IF NOT EXISTS `sleipnir`.`metrics` ON CLUSTER `main` ("));
+    assert!(ddl.ends_with(
+        "ENGINE = ReplicatedMergeTree ORDER BY (path, date, timestamp) TTL date + INTERVAL 90 DAY"
+    ));
+    assert!(!ddl.contains("PARTITION BY"));
+}
+
+#[test]
+fn test_ch_schema_mapping() {
+    assert_eq!(
+        Schema::mapping().create_table("sleipnir", "mapping"),
+        "CREATE TABLE IF NOT EXISTS `sleipnir`.`mapping` (\
+         token String, cleartext String, timestamp Int64) \
+         ENGINE = ReplacingMergeTree ORDER BY token"
+    );
+}
+
+fn column(name: &str, column_type: &str, default_type: &str) -> Column {
+    Column {
+        name: name.to_string(),
+        column_type: column_type.to_string(),
+        default_type: default_type.to_string(),
+    }
+}
+
+#[test]
+fn test_ch_diff_matches() {
+    let columns = vec![
+        column("path", "LowCardinality(String)", ""),
+        column("value", "Float64", ""),
+        column("timestamp", "Int64", ""),
+        column("time", "DateTime", "DEFAULT"),
+        column("date", "Date", "DEFAULT"),
+    ];
+
+    assert!(diff(Metric::COLUMNS, &columns).is_empty());
+}
+
+#[test]
+fn test_ch_diff_mismatch() {
+    let columns = vec![
+        column("path", "String", "MATERIALIZED"),
+        column("value", "Float32", ""),
+        column("ts", "Int64", ""),
+    ];
+
+    assert_eq!(
+        diff(Metric::COLUMNS, &columns),
+        vec![
+            "column `path` is MATERIALIZED, it can't be written",
+            "column `value` is Float32, expected Float64",
+            "missing column `timestamp` Int64",
+        ]
+    );
+}
+
+#[test]
+fn test_ch_schema_error() {
+    let describe = SchemaError::Describe {
+        table: "sleipnir.metrics".to_string(),
+        error: "connection refused".to_string(),
+    };
+    assert_eq!(
+        describe.to_string(),
+        "unable to describe sleipnir.metrics: connection refused"
+    );
+
+    let mismatch = SchemaError::Mismatch {
+        table: "sleipnir.metrics".to_string(),
+        diff: vec![
+            "missing column `value` Float64".to_string(),
+            "column `timestamp` is DateTime, expected Int64".to_string(),
+        ],
+    };
+    assert_eq!(
+        mismatch.to_string(),
+        "sleipnir.metrics schema mismatch: missing column `value` Float64; column `timestamp` is DateTime, expected Int64"
+    );
+}
@@ -0,0 +1,97 @@
+// Bounded map of obfuscation tokens to fingerprints of their cleartext,
+// flags when two different inputs produce the same token, i.e. when
+// hashes are too short for the number of series.
+//
+// Generations are kept per shard as in mapping::Seen, tokens are
+// sampled by their hash seeded per process, so a process always checks
+// the same tokens, restarts and replicas check other ones.
+// Cleartext itself is kept only on request (debug logging).
+use ahash::RandomState;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+const SHARDS: usize = 16;
+
+struct Entry {
+    fingerprint: u128,
+    cleartext: Option<Box<str>>,
+}
+
+#[derive(Default)]
+struct Generations {
+    current: HashMap<Box<str>, Entry>,
+    previous: HashMap<Box<str>, Entry>,
+}
+
+// different cleartext seen for the same token
+#[derive(Debug, PartialEq, Eq)]
+pub struct Collision {
+    // previous cleartext, if kept
+    pub previous: Option<String>,
+}
+
+pub struct Detector {
+    state: RandomState,
+    shards: Vec<Mutex<Generations>>,
+    // per shard generation size
+    capacity: usize,
+    // check one of `sample` tokens
+    sample: u64,
+    keep_cleartext: bool,
+}
+
+// stable 128 bits fingerprint of cleartext
+fn fingerprint(cleartext: &str) -> u128 {
+    let hash = blake3::hash(cleartext.as_bytes());
+    u128::from_be_bytes(hash.as_bytes()[..16].try_into().unwrap())
+}
+
+impl Detector {
+    // remember up to `capacity` tokens (at least, up to twice as much)
+    pub fn new(capacity: usize, sample: u64, keep_cleartext: bool) -> Self {
+        Self {
+            state: RandomState::new(),
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+            capacity: capacity.div_ceil(SHARDS).max(1),
+            sample: sample.max(1),
+            keep_cleartext,
+        }
+    }
+
+    // check token against the previously seen cleartext
+    pub fn check(&self, cleartext: &str, token: &str) -> Result<(), Collision> {
+        let hash = self.state.hash_one(token);
+        if !(hash / SHARDS as u64).is_multiple_of(self.sample) {
+            return Ok(());
+        }
+
+        let fingerprint = fingerprint(cleartext);
+        let mut shard = self.shards[hash as usize % SHARDS].lock().unwrap();
+
+        // keep recently seen tokens in current generation
+        if let Some((token, entry)) = shard.previous.remove_entry(token) {
+            shard.current.insert(token, entry);
+        }
+
+        match shard.current.get(token) {
+            Some(entry) if entry.fingerprint == fingerprint => Ok(()),
+            Some(entry) => Err(Collision {
+                previous: entry.cleartext.as_deref().map(str::to_string),
+            }),
+            None => {
+                if shard.current.len() >= self.capacity {
+                    shard.previous = std::mem::take(&mut shard.current);
+                }
+                let entry = Entry {
+                    fingerprint,
+                    cleartext: self.keep_cleartext.then(|| cleartext.into()),
+                };
+                shard.current.insert(token.into(), entry);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,67 @@
+use super::*;
+
+#[test]
+fn test_collision_same_cleartext() {
+    let detector = Detector::new(100, 1, false);
+
+    assert!(detector.check("cpu.usage", "obf1_0011223344556677").is_ok());
+    assert!(detector.check("cpu.usage", "obf1_0011223344556677").is_ok());
+    assert!(detector.check("cpu.idle", "obf1_8899aabbccddeeff").is_ok());
+}
+
+#[test]
+fn test_collision_detected() {
+    let detector = Detector::new(100, 1, true);
+
+    assert!(detector.check("cpu.usage", "obf1_0011223344556677").is_ok());
+    assert_eq!(
+        detector.check("cpu.idle", "obf1_0011223344556677"),
+        Err(Collision {
+            previous: Some("cpu.usage".to_string())
+        })
+    );
+
+    let detector = Detector::new(100, 1, false);
+    detector
+        .check("cpu.usage", "obf1_0011223344556677")
+        .unwrap();
+    assert_eq!(
+        detector.check("cpu.idle", "obf1_0011223344556677"),
+        Err(Collision { previous: None })
+    );
+}
+
+#[test]
+fn test_collision_bounded() {
+    let detector = Detector::new(SHARDS * 10, 1, false);
+
+    for i in 0..10_000 {
+        detector
+            .check(&i.to_string(), &format!("obf1_{:016x}", i))
+            .unwrap();
+    }
+
+    for shard in &detector.shards {
+        let shard = shard.lock().unwrap();
+        assert!(shard.current.len() <= 10);
+        assert!(shard.previous.len() <= 10);
+    }
+}
+
+#[test]
+fn test_collision_sampled() {
+    let detector = Detector::new(100_000, 4, false);
+
+    for i in 0..10_000 {
+        detector
+            .check(&i.to_string(), &format!("obf1_{:016x}", i))
+            .unwrap();
+    }
+
+    let kept: usize = detector
+        .shards
+        .iter()
+        .map(|shard| shard.lock().unwrap().current.len())
+        .sum();
+    assert!((2_000..3_000).contains(&kept));
+}
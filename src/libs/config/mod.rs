@@ -2,6 +2,25 @@ mod tools;
 
 use serde::Deserialize;
 
+// password or other secret setting, never printed
+#[derive(Deserialize, Default, Clone)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl std::ops::Deref for Secret {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct PrometheusLabels {
     #[serde(default = "default_label_application")]
@@ -19,7 +38,7 @@ pub struct PrometheusLabels {
 pub struct Config {
     // required params
     pub ch_url: String,
-    pub ch_password: String,
+    pub ch_password: Secret,
 
     // optional params
     #[serde(default = "default_num_workers")]
@@ -43,6 +62,102 @@ pub struct Config {
     #[serde(default = "default_port")]
     pub port: u16,
 
+    // check tables schema against written rows on startup, readiness
+    // reuses the last check for `ch_validate_interval` seconds
+    #[serde(default = "default_ch_validate")]
+    pub ch_validate: bool,
+    #[serde(default = "default_ch_validate_interval")]
+    pub ch_validate_interval: u64,
+
+    // create database and tables on startup, clauses override
+    // ch::Schema defaults, empty partitioning or TTL disables it
+    #[serde(default)]
+    pub ch_bootstrap: bool,
+    #[serde(default)]
+    pub ch_engine: Option<String>,
+    #[serde(default)]
+    pub ch_partition_by: Option<String>,
+    #[serde(default)]
+    pub ch_order_by: Option<String>,
+    #[serde(default)]
+    pub ch_ttl: Option<String>,
+    #[serde(default)]
+    pub ch_cluster: Option<String>,
+
+    // obfuscation mapping table, disabled without url
+    #[serde(default)]
+    pub map_ch_url: Option<String>,
+    #[serde(default)]
+    pub map_ch_password: Secret,
+    #[serde(default = "default_username")]
+    pub map_ch_username: String,
+    #[serde(default = "default_database")]
+    pub map_ch_database: String,
+    #[serde(default = "default_map_table")]
+    pub map_ch_table: String,
+    #[serde(default = "default_map_seen_size")]
+    pub map_seen_size: u32,
+
+    // obfuscation collision detector, disabled with zero size,
+    // one of `collision_sample` tokens is checked
+    #[serde(default)]
+    pub collision_check_size: u32,
+    #[serde(default = "default_collision_sample")]
+    pub collision_sample: u32,
+
+    // obfuscated paths cache of hot series, disabled with zero size
+    #[serde(default)]
+    pub obf_cache_size: u32,
+
+    // metric names rewrite rules file (carbon rewrite-rules.conf)
+    #[serde(default)]
+    pub rewrite_rules: Option<String>,
+
+    // allow and block lists files (carbon whitelist.conf/blacklist.conf)
+    #[serde(default)]
+    pub allow_list: Option<String>,
+    #[serde(default)]
+    pub block_list: Option<String>,
+
+    // graphite templates file, extracts tags from dotted names
+    #[serde(default)]
+    pub templates: Option<String>,
+
+    // value transforms file, see transform module
+    #[serde(default)]
+    pub transforms: Option<String>,
+
+    // personal data detection, built-in patterns and custom patterns
+    // file, matches are hashed or redacted, see pii module
+    #[serde(default)]
+    pub pii_detect: Vec<String>,
+    #[serde(default)]
+    pub pii_patterns: Option<String>,
+    #[serde(default = "default_pii_action")]
+    pub pii_action: String,
+
+    // k-anonymity, tag values seen on fewer than `kanon_k` series within
+    // `kanon_window` seconds are bucketed, disabled with zero k
+    #[serde(default)]
+    pub kanon_k: u32,
+    #[serde(default = "default_kanon_window")]
+    pub kanon_window: u64,
+    #[serde(default = "default_kanon_keys")]
+    pub kanon_keys: Vec<String>,
+    #[serde(default = "default_kanon_size")]
+    pub kanon_size: u32,
+
+    // output leak guard, allowed cleartext patterns of name segments,
+    // tag keys and tag values, see obf::Guard
+    #[serde(default)]
+    pub leak_guard: bool,
+    #[serde(default)]
+    pub guard_segments: Option<String>,
+    #[serde(default)]
+    pub guard_tag_keys: Option<String>,
+    #[serde(default)]
+    pub guard_tag_values: Option<String>,
+
     // prometheus client
     #[serde(default, flatten)]
     pub labels: PrometheusLabels,
@@ -52,6 +167,107 @@ pub struct Config {
     pub prometheus_port: u16,
 }
 
+/*
+Obfuscation configuration, loaded on its own so offline
+tools don't need clickhouse and listener settings.
+*/
+#[derive(Deserialize)]
+pub struct ObfConfig {
+    // obfuscation limits
+    #[serde(default = "default_max_name_len")]
+    pub max_name_len: u16,
+    #[serde(default = "default_max_tags")]
+    pub max_tags: u8,
+    #[serde(default = "default_max_tag_key_len")]
+    pub max_tag_key_len: u16,
+    #[serde(default = "default_canonical_tags")]
+    pub canonical_tags: bool,
+
+    // obfuscation secret, inline or from file
+    #[serde(default)]
+    pub obf_key: Option<String>,
+    #[serde(default)]
+    pub obf_key_file: Option<String>,
+    #[serde(default = "default_obf_key_version")]
+    pub obf_key_version: u32,
+    #[serde(default)]
+    pub obf_segments: bool,
+
+    // obfuscation strategy, `hash` or `encrypt`
+    #[serde(default = "default_obf_mode")]
+    pub obf_mode: String,
+
+    // token format, prefix template could contain `{version}`
+    #[serde(default)]
+    pub obf_prefix: Option<String>,
+    #[serde(default = "default_obf_hash_len")]
+    pub obf_hash_len: u8,
+    #[serde(default = "default_obf_encoding")]
+    pub obf_encoding: String,
+
+    // cleartext rules, see obf::Cleartext
+    #[serde(default)]
+    pub clear_tag_keys: Vec<String>,
+    #[serde(default)]
+    pub clear_tag_values: Option<String>,
+    #[serde(default)]
+    pub clear_segments: Vec<usize>,
+
+    // tag keys obfuscated too, `*` for all keys
+    #[serde(default)]
+    pub hash_tag_keys: Vec<String>,
+
+    // key rotation, previous key is used until the end of transition
+    // window (unix timestamp), previous mode and version default to the
+    // current mode and version - 1
+    #[serde(default)]
+    pub obf_rotation_until: Option<i64>,
+    #[serde(default)]
+    pub obf_prev_key: Option<String>,
+    #[serde(default)]
+    pub obf_prev_key_file: Option<String>,
+    #[serde(default)]
+    pub obf_prev_key_version: Option<u32>,
+    #[serde(default)]
+    pub obf_prev_mode: Option<String>,
+
+    // obfuscator per routing rule file, see obf::Routes
+    #[serde(default)]
+    pub obf_routes: Option<String>,
+}
+
+// keys are never printed, only whether they're set
+impl std::fmt::Debug for ObfConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redacted = |key: &Option<String>| key.as_ref().map(|_| "<redacted>");
+
+        f.debug_struct("ObfConfig")
+            .field("max_name_len", &self.max_name_len)
+            .field("max_tags", &self.max_tags)
+            .field("max_tag_key_len", &self.max_tag_key_len)
+            .field("canonical_tags", &self.canonical_tags)
+            .field("obf_key", &redacted(&self.obf_key))
+            .field("obf_key_file", &self.obf_key_file)
+            .field("obf_key_version", &self.obf_key_version)
+            .field("obf_segments", &self.obf_segments)
+            .field("obf_mode", &self.obf_mode)
+            .field("obf_prefix", &self.obf_prefix)
+            .field("obf_hash_len", &self.obf_hash_len)
+            .field("obf_encoding", &self.obf_encoding)
+            .field("clear_tag_keys", &self.clear_tag_keys)
+            .field("clear_tag_values", &self.clear_tag_values)
+            .field("clear_segments", &self.clear_segments)
+            .field("hash_tag_keys", &self.hash_tag_keys)
+            .field("obf_rotation_until", &self.obf_rotation_until)
+            .field("obf_prev_key", &redacted(&self.obf_prev_key))
+            .field("obf_prev_key_file", &self.obf_prev_key_file)
+            .field("obf_prev_key_version", &self.obf_prev_key_version)
+            .field("obf_prev_mode", &self.obf_prev_mode)
+            .field("obf_routes", &self.obf_routes)
+            .finish()
+    }
+}
+
 /// Defaults
 fn default_username() -> String {
     "default".to_string()
@@ -62,6 +278,33 @@ fn default_table() -> String {
 fn default_database() -> String {
     "sleipnir".to_string()
 }
+fn default_map_table() -> String {
+    "mapping".to_string()
+}
+fn default_map_seen_size() -> u32 {
+    1_000_000
+}
+fn default_ch_validate() -> bool {
+    true
+}
+fn default_ch_validate_interval() -> u64 {
+    60
+}
+fn default_collision_sample() -> u32 {
+    1
+}
+fn default_kanon_window() -> u64 {
+    3600
+}
+fn default_kanon_keys() -> Vec<String> {
+    vec!["*".to_string()]
+}
+fn default_kanon_size() -> u32 {
+    1_000_000
+}
+fn default_pii_action() -> String {
+    "hash".to_string()
+}
 fn default_host() -> String {
     "localhost".to_string()
 }
@@ -85,6 +328,30 @@ fn default_num_workers() -> u8 {
 fn default_channel_buffer() -> u32 {
     10000
 }
+fn default_max_name_len() -> u16 {
+    1024
+}
+fn default_max_tags() -> u8 {
+    64
+}
+fn default_max_tag_key_len() -> u16 {
+    128
+}
+fn default_canonical_tags() -> bool {
+    true
+}
+fn default_obf_key_version() -> u32 {
+    1
+}
+fn default_obf_mode() -> String {
+    "hash".to_string()
+}
+fn default_obf_hash_len() -> u8 {
+    8
+}
+fn default_obf_encoding() -> String {
+    "hex".to_string()
+}
 
 // Prometheus Client Defaults
 fn default_label_application() -> String {
@@ -117,5 +384,11 @@ pub fn load() -> Config {
     envy::prefixed("APP_").from_env().unwrap()
 }
 
+pub fn load_obf() -> ObfConfig {
+    dotenvy::dotenv().ok();
+
+    envy::prefixed("APP_").from_env().unwrap()
+}
+
 #[cfg(test)]
 mod tests;
@@ -19,7 +19,7 @@ fn test_config_load_all_values() {
 
     assert_eq!(config.host, "example.com");
     assert_eq!(config.ch_url, "ch.example.com");
-    assert_eq!(config.ch_password, "password");
+    assert_eq!(&*config.ch_password, "password");
     assert_eq!(config.port, 3000);
 }
 
@@ -42,3 +42,143 @@ fn test_config_load_defaults() {
     assert_eq!(config.host, "localhost");
     assert_eq!(config.port, 8080);
 }
+
+#[test]
+#[serial]
+fn test_config_load_lists() {
+    let mut env = EnvSetter::new();
+    env.set("APP_CH_URL", "ch.example.com");
+    env.set("APP_CH_PASSWORD", "password");
+    env.set("APP_CLEAR_TAG_KEYS", "env,dc");
+    env.set("APP_CLEAR_SEGMENTS", "0,2");
+
+    let config = load_obf();
+
+    assert_eq!(config.clear_tag_keys, vec!["env", "dc"]);
+    assert_eq!(config.clear_segments, vec![0, 2]);
+    assert!(config.clear_tag_values.is_none());
+    assert_eq!(config.obf_mode, "hash");
+}
+
+#[test]
+#[serial]
+fn test_config_load_rotation() {
+    let mut env = EnvSetter::new();
+    env.set("APP_OBF_KEY", "new secret");
+    env.set("APP_OBF_KEY_VERSION", "2");
+    env.set("APP_OBF_PREV_KEY", "secret");
+    env.set("APP_OBF_ROTATION_UNTIL", "1700000000");
+
+    let config = load_obf();
+
+    assert_eq!(config.obf_rotation_until, Some(1700000000));
+    assert_eq!(config.obf_prev_key.as_deref(), Some("secret"));
+    assert!(config.obf_prev_key_version.is_none());
+    assert!(config.obf_prev_mode.is_none());
+}
+
+#[test]
+#[serial]
+fn test_config_obf_keys_redacted() {
+    let mut env = EnvSetter::new();
+    env.set("APP_OBF_KEY", "new secret");
+    env.set("APP_OBF_PREV_KEY", "old secret");
+
+    let debug = format!("{:?}", load_obf());
+
+    assert!(!debug.contains("secret"));
+    assert!(debug.contains("obf_key: Some(\"<redacted>\")"));
+}
+
+#[test]
+#[serial]
+fn test_config_obf_empty_key() {
+    let mut env = EnvSetter::new();
+    env.set("APP_OBF_KEY", "");
+
+    let e = crate::libs::obf::Options::load(&load_obf()).unwrap_err();
+    assert_eq!(e, "obfuscation key is empty");
+}
+
+#[test]
+#[serial]
+fn test_config_clear_tag_values_anchored() {
+    use crate::libs::graphite::GraphiteMetric;
+    use crate::libs::obf;
+
+    let mut env = EnvSetter::new();
+    env.set("APP_CLEAR_TAG_VALUES", "prod|staging");
+
+    let options = obf::Options::load(&load_obf()).unwrap();
+    let clear = GraphiteMetric::parse("cpu;env=prod 1 1").unwrap();
+    let partial = GraphiteMetric::parse("cpu;env=prod-customer42-db 1 1").unwrap();
+
+    assert!(
+        obf::obfuscate(&clear, &options)
+            .unwrap()
+            .ends_with(";env=prod")
+    );
+    let path = obf::obfuscate(&partial, &options).unwrap();
+    assert!(!path.contains("customer42"));
+}
+
+#[test]
+#[serial]
+fn test_config_obf_format_checked() {
+    use crate::libs::obf;
+
+    let mut env = EnvSetter::new();
+    env.set("APP_OBF_PREFIX", "m.{version}_");
+    assert!(obf::Options::load(&load_obf()).is_err());
+
+    // legacy tokens have no format
+    env.set("APP_OBF_PREFIX", "m{version}_");
+    let e = obf::Options::load(&load_obf()).unwrap_err();
+    assert_eq!(e, "token format requires a key");
+
+    env.set("APP_OBF_KEY", "secret");
+    let options = obf::Options::load(&load_obf()).unwrap();
+    assert_eq!(options.strategy.prefix(), "m1_");
+    assert_eq!(options.strategies.keyed.unwrap().prefix(), "m1_");
+    assert_eq!(options.strategies.encrypted.unwrap().prefix(), "enc1_");
+}
+
+#[test]
+#[serial]
+fn test_config_passwords_redacted() {
+    let mut env = EnvSetter::new();
+    env.set("APP_CH_URL", "ch.example.com");
+    env.set("APP_CH_PASSWORD", "ingest password");
+    env.set("APP_MAP_CH_PASSWORD", "mapping password");
+
+    let config = load();
+    let debug = format!("{:?}", config);
+
+    assert!(!debug.contains("password\""));
+    assert!(debug.contains("map_ch_password: <redacted>"));
+    assert_eq!(&*config.map_ch_password, "mapping password");
+}
+
+#[test]
+#[serial]
+fn test_config_obf_key_version_zero() {
+    use crate::libs::obf;
+
+    // previous version defaults to 0 with the default current version
+    let mut env = EnvSetter::new();
+    env.set("APP_OBF_KEY", "new secret");
+    env.set("APP_OBF_PREV_KEY", "secret");
+    env.set("APP_OBF_ROTATION_UNTIL", "1700000000");
+    let e = obf::Options::load(&load_obf()).unwrap_err();
+    assert_eq!(e, "key version 0 is reserved for legacy tokens");
+
+    env.set("APP_OBF_KEY_VERSION", "2");
+    let options = obf::Options::load(&load_obf()).unwrap();
+    assert_eq!(options.rotation.unwrap().strategy.version(), 1);
+
+    // previous legacy tokens need no version
+    env.del("APP_OBF_PREV_KEY");
+    env.set("APP_OBF_KEY_VERSION", "1");
+    let options = obf::Options::load(&load_obf()).unwrap();
+    assert_eq!(options.rotation.unwrap().strategy.version(), 0);
+}
@@ -0,0 +1,209 @@
+// Allow and block lists for metrics, compatible with carbon's
+// whitelist.conf/blacklist.conf: one regular expression per line
+// matched against the whole metric path (`name;key=value;...`).
+//
+// Lines prefixed with `glob:` are graphite globs matched against the
+// metric name, optionally followed by tag conditions which all have
+// to be present on the metric:
+//
+//     glob:servers.*.cpu.{user,system};env=dev*
+//
+// `*` and `?` do not cross `.` in names, `{a,b}`, `[abc]` and `[!abc]` are
+// supported.
+use crate::libs::graphite::GraphiteMetric;
+use regex::Regex;
+
+// rule label used when a metric doesn't match any allow rule
+pub const NOT_ALLOWED: &str = "allowlist";
+
+enum Matcher {
+    Regex(Regex),
+    Glob {
+        name: Regex,
+        tags: Vec<(String, Regex)>,
+    },
+}
+
+pub struct Rule {
+    pub pattern: String,
+    matcher: Matcher,
+}
+
+#[derive(Default)]
+pub struct Filter {
+    allow: Vec<Rule>,
+    block: Vec<Rule>,
+}
+
+// translate graphite glob into anchored regex, `any` is wildcard class
+fn glob(pattern: &str, any: &str) -> Result<Regex, String> {
+    let mut re = String::from("^");
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => re.push_str(&format!("{}*", any)),
+            '?' => re.push_str(any),
+            '{' => {
+                let mut alts = Vec::new();
+                let mut alt = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(',') => alts.push(std::mem::take(&mut alt)),
+                        Some(c) => alt.push(c),
+                        None => return Err(format!("unclosed '{{' in glob {:?}", pattern)),
+                    }
+                }
+                alts.push(alt);
+                let alts: Vec<String> = alts.iter().map(|a| regex::escape(a)).collect();
+                re.push_str(&format!("(?:{})", alts.join("|")));
+            }
+            '[' => {
+                re.push('[');
+                // `[!abc]` negates the class, still not crossing `.` in names
+                if chars.clone().next() == Some('!') {
+                    chars.next();
+                    re.push('^');
+                    if any == "[^.]" {
+                        re.push_str("\\.");
+                    }
+                }
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c @ ('\\' | '[')) => {
+                            re.push('\\');
+                            re.push(c);
+                        }
+                        Some(c) => re.push(c),
+                        None => return Err(format!("unclosed '[' in glob {:?}", pattern)),
+                    }
+                }
+                re.push(']');
+            }
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    re.push('$');
+    Regex::new(&re).map_err(|e| format!("invalid glob {:?}: {}", pattern, e))
+}
+
+impl Rule {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let matcher = match pattern.strip_prefix("glob:") {
+            Some(globs) => {
+                let mut parts = globs.split(';');
+                let name = glob(parts.next().unwrap_or_default(), "[^.]")?;
+                let tags = parts
+                    .map(|tag| {
+                        let (key, value) = tag
+                            .split_once('=')
+                            .ok_or_else(|| format!("invalid tag condition {:?}", tag))?;
+                        Ok((key.to_string(), glob(value, ".")?))
+                    })
+                    .collect::<Result<_, String>>()?;
+                Matcher::Glob { name, tags }
+            }
+            None => {
+                let regex = Regex::new(pattern.strip_prefix("regex:").unwrap_or(pattern))
+                    .map_err(|e| format!("invalid pattern {:?}: {}", pattern, e))?;
+                Matcher::Regex(regex)
+            }
+        };
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            matcher,
+        })
+    }
+
+    // match metric, `path` is the whole metric path, see `path()`
+    pub fn matches(&self, metric: &GraphiteMetric, path: &str) -> bool {
+        match &self.matcher {
+            Matcher::Regex(regex) => regex.is_match(path),
+            Matcher::Glob { name, tags } => {
+                name.is_match(metric.name)
+                    && tags.iter().all(|(key, value)| {
+                        metric
+                            .tags
+                            .iter()
+                            .any(|(k, v)| k == key && value.is_match(v))
+                    })
+            }
+        }
+    }
+}
+
+// whole metric path (`name;key=value;...`) matched by regular expressions
+pub fn path(metric: &GraphiteMetric) -> String {
+    let mut path = String::from(metric.name);
+    for (key, value) in &metric.tags {
+        path.push(';');
+        path.push_str(key);
+        path.push('=');
+        path.push_str(value);
+    }
+    path
+}
+
+// parse rules, one per line, empty lines and `#` comments are skipped
+pub fn parse(content: &str) -> Result<Vec<Rule>, String> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| Rule::new(line).map_err(|e| format!("line {}: {}", n + 1, e)))
+        .collect()
+}
+
+fn load(path: &str) -> Result<Vec<Rule>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("unable to read filter rules {}: {}", path, e))?;
+    parse(&content).map_err(|e| format!("{}: {}", path, e))
+}
+
+impl Filter {
+    pub fn new(allow: Vec<Rule>, block: Vec<Rule>) -> Self {
+        Self { allow, block }
+    }
+
+    // load allow and block lists from files, missing lists are empty
+    pub fn load(allow: Option<&str>, block: Option<&str>) -> Result<Self, String> {
+        Ok(Self {
+            allow: allow.map(load).transpose()?.unwrap_or_default(),
+            block: block.map(load).transpose()?.unwrap_or_default(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.block.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.allow.len() + self.block.len()
+    }
+
+    // check metric, returns the rule which drops it
+    pub fn check(&self, metric: &GraphiteMetric) -> Result<(), &str> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let path = path(metric);
+
+        if !self.allow.is_empty() && !self.allow.iter().any(|r| r.matches(metric, &path)) {
+            return Err(NOT_ALLOWED);
+        }
+
+        match self.block.iter().find(|r| r.matches(metric, &path)) {
+            Some(rule) => Err(&rule.pattern),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,64 @@
+use super::*;
+
+fn check<'a>(filter: &'a Filter, line: &str) -> Result<(), &'a str> {
+    let metric = GraphiteMetric::parse(line).unwrap();
+    filter.check(&metric)
+}
+
+#[test]
+fn test_filter_empty_passes() {
+    let filter = Filter::default();
+
+    assert_eq!(check(&filter, "any.metric;env=dev 1 1"), Ok(()));
+}
+
+#[test]
+fn test_filter_block_regex() {
+    let block = parse("# junk from broken agents\n^collectd\\.\n;env=dev(;|$)\n").unwrap();
+    let filter = Filter::new(Vec::new(), block);
+
+    assert_eq!(check(&filter, "collectd.cpu 1 1"), Err("^collectd\\."));
+    assert_eq!(check(&filter, "app.cpu;env=dev 1 1"), Err(";env=dev(;|$)"));
+    assert_eq!(check(&filter, "app.cpu;env=devel 1 1"), Ok(()));
+}
+
+#[test]
+fn test_filter_allow_glob() {
+    let allow = parse("glob:servers.*.cpu.{user,system}\nglob:app.*;env=prod*\n").unwrap();
+    let filter = Filter::new(allow, Vec::new());
+
+    assert_eq!(check(&filter, "servers.web01.cpu.user 1 1"), Ok(()));
+    assert_eq!(check(&filter, "app.requests;env=production 1 1"), Ok(()));
+    assert_eq!(
+        check(&filter, "servers.web01.eu.cpu.user 1 1"),
+        Err(NOT_ALLOWED)
+    );
+    assert_eq!(check(&filter, "app.requests;env=dev 1 1"), Err(NOT_ALLOWED));
+    assert_eq!(check(&filter, "app.requests 1 1"), Err(NOT_ALLOWED));
+}
+
+#[test]
+fn test_filter_glob_negated_class() {
+    let block = parse("glob:servers.web0[!12].cpu\nglob:app.*;dc=[!a]*\n").unwrap();
+    let filter = Filter::new(Vec::new(), block);
+
+    assert_eq!(check(&filter, "servers.web01.cpu 1 1"), Ok(()));
+    assert_eq!(check(&filter, "servers.web02.cpu 1 1"), Ok(()));
+    assert_eq!(check(&filter, "servers.web0.x.cpu 1 1"), Ok(()));
+    assert_eq!(
+        check(&filter, "servers.web03.cpu 1 1"),
+        Err("glob:servers.web0[!12].cpu")
+    );
+    assert_eq!(check(&filter, "app.cpu;dc=ams 1 1"), Ok(()));
+    assert_eq!(
+        check(&filter, "app.cpu;dc=fra 1 1"),
+        Err("glob:app.*;dc=[!a]*")
+    );
+}
+
+#[test]
+fn test_filter_invalid() {
+    assert!(parse("(unclosed").is_err());
+    assert!(parse("glob:servers.{a,b").is_err());
+    assert!(parse("glob:app;env").is_err());
+}
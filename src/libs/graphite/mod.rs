@@ -1,5 +1,9 @@
+use memchr::{memchr, memchr2};
 use smallvec::SmallVec;
 
+pub mod scalar;
+
+#[derive(Clone)]
 pub struct GraphiteMetric<'a> {
     pub name: &'a str,
     pub tags: SmallVec<[(&'a str, &'a str); 16]>,
@@ -7,47 +11,57 @@ pub struct GraphiteMetric<'a> {
     pub timestamp: i64,
 }
 
+// fields separator, any run of spaces or tabs
+#[inline(always)]
+fn is_blank(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
 impl<'a> GraphiteMetric<'a> {
     #[inline(always)]
     pub fn parse(line: &'a str) -> Result<Self, String> {
+        // strip line ending left by CRLF senders
+        let line = line.trim_end_matches(['\r', '\n']);
         let bytes = line.as_bytes();
+        let len = bytes.len();
+
+        // try to search three fields separated by blanks,
+        // blank runs are short so only field ends are vectorized
+        let mut fields = [(0usize, 0usize); 3];
+        let mut i = 0;
 
-        // try to search first two spaces
-        let (mut sp1, mut sp2) = (usize::MAX, usize::MAX);
-
-        for (i, &b) in bytes.iter().enumerate() {
-            if b == b' ' {
-                if sp1 == usize::MAX {
-                    sp1 = i;
-                } else {
-                    sp2 = i;
-                    break;
-                }
+        for field in fields.iter_mut() {
+            while i < len && is_blank(bytes[i]) {
+                i += 1;
+            }
+            let start = i;
+            i = memchr2(b' ', b'\t', &bytes[i..]).map_or(len, |p| i + p);
+            if start == i {
+                return Err(format!("invalid graphite line: {:?}", line));
             }
+            *field = (start, i);
         }
 
-        if sp1 == usize::MAX || sp2 == usize::MAX {
+        // only trailing blanks are allowed after timestamp
+        if bytes[i..].iter().any(|&b| !is_blank(b)) {
             return Err(format!("invalid graphite line: {:?}", line));
         }
 
         // collect metric (path), value and timestamp
-        let metric = &line[..sp1];
-        let value_str = &line[sp1 + 1..sp2];
-        let ts_str = &line[sp2 + 1..];
+        let metric = &line[fields[0].0..fields[0].1];
+        let value_str = &line[fields[1].0..fields[1].1];
+        let ts_str = &line[fields[2].0..fields[2].1];
 
         // convert value and timestamp
-        let value: f64 = value_str.parse().map_err(|_| "bad value")?;
+        let value: f64 = fast_float2::parse(value_str).map_err(|_| "bad value")?;
         let timestamp: i64 = ts_str.parse().map_err(|_| "bad ts")?;
 
         // parse metric (path)
         let mb = metric.as_bytes();
         let mlen = mb.len();
-        let mut i = 0;
 
         // collect name
-        while i < mlen && mb[i] != b';' {
-            i += 1;
-        }
+        let mut i = memchr(b';', mb).unwrap_or(mlen);
         let name = &metric[..i];
 
         // ... collect tags
@@ -60,23 +74,18 @@ impl<'a> GraphiteMetric<'a> {
             }
 
             let key_start = i;
-            while i < mlen && mb[i] != b'=' {
-                i += 1;
-            }
-            if i >= mlen {
+            let Some(eq) = memchr(b'=', &mb[i..]) else {
                 break;
-            }
-            let key_end = i;
+            };
+            let key_end = i + eq;
 
-            i += 1; // skip '='
+            i = key_end + 1; // skip '='
             if i >= mlen {
                 break;
             }
 
             let val_start = i;
-            while i < mlen && mb[i] != b';' {
-                i += 1;
-            }
+            i = memchr(b';', &mb[i..]).map_or(mlen, |p| i + p);
             let val_end = i;
 
             tags.push((&metric[key_start..key_end], &metric[val_start..val_end]));
@@ -89,4 +98,20 @@ impl<'a> GraphiteMetric<'a> {
             timestamp,
         })
     }
+
+    // sort tags by key, the last one wins on duplicated keys
+    pub fn canonicalize(&mut self) {
+        // stable sort keeps duplicates in input order
+        self.tags.sort_by(|a, b| a.0.cmp(b.0));
+        self.tags.dedup_by(|next, prev| {
+            if next.0 == prev.0 {
+                *prev = *next;
+                return true;
+            }
+            false
+        });
+    }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,98 @@
+// Reference scalar parser, kept for benchmarks and equivalence tests
+use super::GraphiteMetric;
+use smallvec::SmallVec;
+
+// fields separator, any run of spaces or tabs
+#[inline(always)]
+fn is_blank(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+pub fn parse(line: &str) -> Result<GraphiteMetric<'_>, String> {
+    // strip line ending left by CRLF senders
+    let line = line.trim_end_matches(['\r', '\n']);
+    let bytes = line.as_bytes();
+    let len = bytes.len();
+
+    // try to search three fields separated by blanks
+    let mut fields = [(0usize, 0usize); 3];
+    let mut i = 0;
+
+    for field in fields.iter_mut() {
+        while i < len && is_blank(bytes[i]) {
+            i += 1;
+        }
+        let start = i;
+        while i < len && !is_blank(bytes[i]) {
+            i += 1;
+        }
+        if start == i {
+            return Err(format!("invalid graphite line: {:?}", line));
+        }
+        *field = (start, i);
+    }
+
+    // only trailing blanks are allowed after timestamp
+    if bytes[i..].iter().any(|&b| !is_blank(b)) {
+        return Err(format!("invalid graphite line: {:?}", line));
+    }
+
+    // collect metric (path), value and timestamp
+    let metric = &line[fields[0].0..fields[0].1];
+    let value_str = &line[fields[1].0..fields[1].1];
+    let ts_str = &line[fields[2].0..fields[2].1];
+
+    // convert value and timestamp
+    let value: f64 = value_str.parse().map_err(|_| "bad value")?;
+    let timestamp: i64 = ts_str.parse().map_err(|_| "bad ts")?;
+
+    // parse metric (path)
+    let mb = metric.as_bytes();
+    let mlen = mb.len();
+    let mut i = 0;
+
+    // collect name
+    while i < mlen && mb[i] != b';' {
+        i += 1;
+    }
+    let name = &metric[..i];
+
+    // ... collect tags
+    let mut tags = SmallVec::<[(&str, &str); 16]>::new();
+
+    while i < mlen {
+        i += 1; // skip ';'
+        if i >= mlen {
+            break;
+        }
+
+        let key_start = i;
+        while i < mlen && mb[i] != b'=' {
+            i += 1;
+        }
+        if i >= mlen {
+            break;
+        }
+        let key_end = i;
+
+        i += 1; // skip '='
+        if i >= mlen {
+            break;
+        }
+
+        let val_start = i;
+        while i < mlen && mb[i] != b';' {
+            i += 1;
+        }
+        let val_end = i;
+
+        tags.push((&metric[key_start..key_end], &metric[val_start..val_end]));
+    }
+
+    Ok(GraphiteMetric {
+        name,
+        tags,
+        value,
+        timestamp,
+    })
+}
@@ -27,6 +27,117 @@ fn test_graphite_parse_with_tags() {
     assert_eq!(metric.value, 42.5);
     assert_eq!(metric.timestamp, 1234567890);
     assert_eq!(metric.tags.len(), 2);
-    assert_eq!(metric.tags.get("host"), Some(&"server1".to_string()));
-    assert_eq!(metric.tags.get("app"), Some(&"my_server".to_string()));
+    assert_eq!(metric.tags[0], ("host", "server1"));
+    assert_eq!(metric.tags[1], ("app", "my_server"));
+}
+
+#[test]
+#[serial]
+fn test_graphite_parse_blank_runs() {
+    let line = "cpu.usage;host=server1 \t  42.5\t1234567890";
+    let result = GraphiteMetric::parse(line);
+
+    assert!(result.is_ok());
+    let metric = result.unwrap();
+    assert_eq!(metric.name, "cpu.usage");
+    assert_eq!(metric.value, 42.5);
+    assert_eq!(metric.timestamp, 1234567890);
+    assert_eq!(metric.tags[0], ("host", "server1"));
+}
+
+#[test]
+#[serial]
+fn test_graphite_parse_crlf() {
+    let line = "cpu.usage 42.5 1234567890\r";
+    let result = GraphiteMetric::parse(line);
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap().timestamp, 1234567890);
+}
+
+#[test]
+#[serial]
+fn test_graphite_parse_invalid() {
+    assert!(GraphiteMetric::parse("cpu.usage 42.5").is_err());
+    assert!(GraphiteMetric::parse("cpu.usage 42.5 1234567890 extra").is_err());
+    assert!(GraphiteMetric::parse(" \t \r").is_err());
+}
+
+// both parsers must agree on result, including errors
+fn assert_same(line: &str) {
+    match (GraphiteMetric::parse(line), scalar::parse(line)) {
+        (Ok(a), Ok(b)) => {
+            assert_eq!(a.name, b.name, "line: {:?}", line);
+            assert_eq!(a.tags, b.tags, "line: {:?}", line);
+            assert!(
+                a.value.to_bits() == b.value.to_bits() || (a.value.is_nan() && b.value.is_nan()),
+                "line: {:?}",
+                line
+            );
+            assert_eq!(a.timestamp, b.timestamp, "line: {:?}", line);
+        }
+        (Err(a), Err(b)) => assert_eq!(a, b, "line: {:?}", line),
+        (a, b) => panic!(
+            "parsers disagree on {:?}: {:?} vs {:?}",
+            line,
+            a.is_ok(),
+            b.is_ok()
+        ),
+    }
+}
+
+#[test]
+#[serial]
+fn test_graphite_parse_same_as_scalar() {
+    for line in [
+        "cpu.usage 42.5 1234567890",
+        "cpu.usage;host=server1;app=my_server 42.5 1234567890",
+        "cpu.usage;host=server1 \t  42.5\t1234567890",
+        "cpu.usage 42.5 1234567890\r",
+        "cpu.usage;;host;=x;a=b=c; -1e-3 -1",
+        "cpu.usage nan 1",
+        "cpu.usage 42.5",
+        "cpu.usage 42.5 1234567890 extra",
+        " \t \r",
+    ] {
+        assert_same(line);
+    }
+}
+
+#[test]
+#[serial]
+fn test_graphite_parse_fuzz_same_as_scalar() {
+    const PIECES: [&str; 24] = [
+        "a", "cpu", ".", ";", "=", " ", "\t", "\r", "\n", "1", "0", "-", "+", "e", "E", ".5",
+        "inf", "NaN", "x", "host", "12345", "\u{e9}", "  ", ";k=v",
+    ];
+
+    // xorshift, deterministic and dependency free
+    let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut next = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    for _ in 0..200_000 {
+        let mut line = String::new();
+        for _ in 0..next() % 16 {
+            line.push_str(PIECES[(next() % PIECES.len() as u64) as usize]);
+        }
+        assert_same(&line);
+    }
+}
+
+#[test]
+#[serial]
+fn test_graphite_canonicalize() {
+    let mut metric = GraphiteMetric::parse("cpu;y=2;x=1;y=3;a=0 1 1").unwrap();
+    metric.canonicalize();
+
+    assert_eq!(
+        metric.tags.as_slice(),
+        &[("a", "0"), ("x", "1"), ("y", "3")]
+    );
 }
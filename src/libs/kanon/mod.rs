@@ -0,0 +1,121 @@
+// k-anonymity bucketing of rare tag values.
+//
+// Distinct series are counted per (tag key, value) pair over a sliding
+// window, values seen on fewer than K series are replaced with the
+// shared `obf::RARE` bucket before obfuscation, so a single customer
+// can't be told apart by its traffic pattern.
+//
+// The window is approximated with two generations per shard, aligned
+// to `window` seconds, counts of the previous generation are carried
+// over on the first sight. Once the number of tracked values reaches the
+// capacity, untracked values are treated as rare.
+use crate::libs::graphite::GraphiteMetric;
+use crate::libs::obf::RARE;
+use ahash::RandomState;
+use smallvec::SmallVec;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+const SHARDS: usize = 16;
+
+// distinct series hashes, up to K of them
+type Series = SmallVec<[u64; 4]>;
+
+#[derive(Default)]
+struct Generations {
+    // number of the current window since epoch
+    number: i64,
+    current: HashMap<u64, Series>,
+    previous: HashMap<u64, Series>,
+}
+
+pub struct Anonymizer {
+    k: usize,
+    window: i64,
+    // tag keys to bucket, `*` for every key
+    keys: Vec<String>,
+    state: RandomState,
+    shards: Vec<Mutex<Generations>>,
+    // per shard generation size
+    capacity: usize,
+}
+
+impl Anonymizer {
+    pub fn new(k: usize, window: u64, keys: Vec<String>, capacity: usize) -> Self {
+        Self {
+            k: k.max(1),
+            window: window.max(1) as i64,
+            keys,
+            state: RandomState::new(),
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+            capacity: capacity.div_ceil(SHARDS).max(1),
+        }
+    }
+
+    // configured entry bucketing the key
+    fn bucketed(&self, key: &str) -> Option<&str> {
+        self.keys
+            .iter()
+            .find(|k| *k == "*" || *k == key)
+            .map(String::as_str)
+    }
+
+    // record series of the value, returns `true` if the value is rare
+    fn observe(&self, key: &str, value: &str, series: u64, now: i64) -> bool {
+        let hash = self.state.hash_one((key, value));
+        let mut shard = self.shards[hash as usize % SHARDS].lock().unwrap();
+
+        let number = now.div_euclid(self.window);
+        // workers may hold `now` of the previous window, never go back
+        if number > shard.number {
+            shard.previous = match number - shard.number {
+                1 => std::mem::take(&mut shard.current),
+                _ => HashMap::new(),
+            };
+            shard.current.clear();
+            shard.number = number;
+        }
+
+        let shard = &mut *shard;
+        if !shard.current.contains_key(&hash) {
+            if shard.current.len() >= self.capacity {
+                return true;
+            }
+            let carried = shard.previous.get(&hash).cloned().unwrap_or_default();
+            shard.current.insert(hash, carried);
+        }
+
+        let seen = shard.current.get_mut(&hash).unwrap();
+        if seen.len() < self.k && !seen.contains(&series) {
+            seen.push(series);
+        }
+        seen.len() < self.k
+    }
+
+    // replace rare tag values, `rare` is called with the configured
+    // entry of every replaced key (e.g. `*`), `now` is unix timestamp
+    pub fn apply<F>(&self, metric: &mut GraphiteMetric, now: i64, mut rare: F)
+    where
+        F: FnMut(&str),
+    {
+        if self.keys.is_empty() || metric.tags.is_empty() {
+            return;
+        }
+
+        let series = self.state.hash_one((metric.name, &metric.tags[..]));
+        for (key, value) in metric.tags.iter_mut() {
+            if *value == RARE {
+                continue;
+            }
+            if let Some(entry) = self.bucketed(key)
+                && self.observe(key, value, series, now)
+            {
+                *value = RARE;
+                rare(entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,88 @@
+use super::*;
+
+fn apply(anonymizer: &Anonymizer, line: &str, now: i64) -> (Vec<String>, Vec<String>) {
+    let mut metric = GraphiteMetric::parse(line).unwrap();
+    let mut rare = Vec::new();
+    anonymizer.apply(&mut metric, now, |key| rare.push(key.to_string()));
+
+    let values = metric.tags.iter().map(|(_, v)| v.to_string()).collect();
+    (values, rare)
+}
+
+#[test]
+fn test_kanon_rare_values() {
+    let anonymizer = Anonymizer::new(3, 3600, vec!["customer".to_string()], 1000);
+
+    // the same series is counted once
+    for _ in 0..10 {
+        let (values, rare) = apply(&anonymizer, "app.requests;customer=acme;dc=eu 1 1", 0);
+        assert_eq!(values, vec![RARE, "eu"]);
+        assert_eq!(rare, vec!["customer"]);
+    }
+
+    apply(&anonymizer, "app.errors;customer=acme;dc=eu 1 1", 0);
+    let (values, rare) = apply(&anonymizer, "app.latency;customer=acme;dc=eu 1 1", 0);
+    assert_eq!(values, vec!["acme", "eu"]);
+    assert!(rare.is_empty());
+
+    let (values, _) = apply(&anonymizer, "app.requests;customer=acme;dc=eu 1 1", 0);
+    assert_eq!(values, vec!["acme", "eu"]);
+}
+
+#[test]
+fn test_kanon_all_keys() {
+    let anonymizer = Anonymizer::new(2, 3600, vec!["*".to_string()], 1000);
+
+    let (values, rare) = apply(&anonymizer, "app.requests;customer=acme;dc=eu 1 1", 0);
+    assert_eq!(values, vec![RARE, RARE]);
+    assert_eq!(rare, vec!["*", "*"]);
+
+    let (values, _) = apply(&anonymizer, "app.errors;customer=other;dc=eu 1 1", 0);
+    assert_eq!(values, vec![RARE, "eu"]);
+}
+
+#[test]
+fn test_kanon_window() {
+    let anonymizer = Anonymizer::new(2, 60, vec!["customer".to_string()], 1000);
+
+    apply(&anonymizer, "app.requests;customer=acme 1 1", 0);
+    apply(&anonymizer, "app.errors;customer=acme 1 1", 0);
+
+    // carried over into the next generation
+    let (values, _) = apply(&anonymizer, "app.requests;customer=acme 1 1", 60);
+    assert_eq!(values, vec!["acme"]);
+
+    // forgotten after two windows without new series
+    apply(&anonymizer, "app.requests;customer=other 1 1", 120);
+    let (values, _) = apply(&anonymizer, "app.requests;customer=acme 1 1", 180);
+    assert_eq!(values, vec![RARE]);
+}
+
+#[test]
+fn test_kanon_late_worker() {
+    let anonymizer = Anonymizer::new(2, 60, vec!["customer".to_string()], 1000);
+
+    apply(&anonymizer, "app.requests;customer=acme 1 1", 60);
+
+    // `now` of the previous window counts into the current generation
+    let (values, _) = apply(&anonymizer, "app.errors;customer=acme 1 1", 59);
+    assert_eq!(values, vec!["acme"]);
+
+    let (values, _) = apply(&anonymizer, "app.latency;customer=acme 1 1", 60);
+    assert_eq!(values, vec!["acme"]);
+}
+
+#[test]
+fn test_kanon_capacity() {
+    let anonymizer = Anonymizer::new(1, 3600, vec!["customer".to_string()], SHARDS);
+
+    let rare = (0..1000)
+        .filter(|i| {
+            let line = format!("app.requests;customer=c{} 1 1", i);
+            apply(&anonymizer, &line, 0).0 == vec![RARE]
+        })
+        .count();
+
+    // untracked values are rare once full
+    assert!(rare > 900);
+}
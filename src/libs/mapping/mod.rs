@@ -0,0 +1,74 @@
+// Bounded set of already recorded obfuscation tokens, so only newly
+// seen (cleartext -> token) pairs are written into the mapping table.
+//
+// Each shard keeps two generations of token hashes, when the current
+// one is full it becomes the previous one and the oldest generation is
+// forgotten. Forgotten tokens are written again when seen next time,
+// which is fine for ReplacingMergeTree mapping table.
+use ahash::RandomState;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+const SHARDS: usize = 16;
+
+#[derive(Default)]
+struct Generations {
+    current: HashSet<u64>,
+    previous: HashSet<u64>,
+}
+
+pub struct Seen {
+    state: RandomState,
+    shards: Vec<Mutex<Generations>>,
+    // per shard generation size
+    capacity: usize,
+}
+
+impl Seen {
+    // remember up to `capacity` tokens (at least, up to twice as much)
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: RandomState::new(),
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+            capacity: capacity.div_ceil(SHARDS).max(1),
+        }
+    }
+
+    fn shard(&self, token: &str) -> (&Mutex<Generations>, u64) {
+        let hash = self.state.hash_one(token);
+        (&self.shards[hash as usize % SHARDS], hash)
+    }
+
+    // remember token, returns `true` if it hasn't been seen before
+    pub fn insert(&self, token: &str) -> bool {
+        let (shard, hash) = self.shard(token);
+        let mut shard = shard.lock().unwrap();
+
+        if shard.current.contains(&hash) {
+            return false;
+        }
+        if shard.previous.remove(&hash) {
+            // keep recently seen tokens in current generation
+            shard.current.insert(hash);
+            return false;
+        }
+
+        if shard.current.len() >= self.capacity {
+            shard.previous = std::mem::take(&mut shard.current);
+        }
+        shard.current.insert(hash);
+        true
+    }
+
+    // forget token, e.g. when its mapping wasn't written
+    pub fn remove(&self, token: &str) {
+        let (shard, hash) = self.shard(token);
+        let mut shard = shard.lock().unwrap();
+
+        shard.current.remove(&hash);
+        shard.previous.remove(&hash);
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,37 @@
+use super::*;
+
+#[test]
+fn test_seen_insert_once() {
+    let seen = Seen::new(100);
+
+    assert!(seen.insert("obf1_0011223344556677"));
+    assert!(!seen.insert("obf1_0011223344556677"));
+    assert!(seen.insert("obf1_8899aabbccddeeff"));
+}
+
+#[test]
+fn test_seen_remove() {
+    let seen = Seen::new(100);
+
+    assert!(seen.insert("obf1_0011223344556677"));
+    seen.remove("obf1_0011223344556677");
+    assert!(seen.insert("obf1_0011223344556677"));
+}
+
+#[test]
+fn test_seen_bounded() {
+    let seen = Seen::new(SHARDS * 10);
+
+    for i in 0..10_000 {
+        seen.insert(&format!("obf1_{:016x}", i));
+    }
+
+    for shard in &seen.shards {
+        let shard = shard.lock().unwrap();
+        assert!(shard.current.len() <= 10);
+        assert!(shard.previous.len() <= 10);
+    }
+
+    // the most recent token is still remembered
+    assert!(!seen.insert(&format!("obf1_{:016x}", 9_999)));
+}
@@ -1,6 +1,15 @@
+pub mod cache;
 pub mod ch;
+pub mod collision;
 pub mod config;
+pub mod filter;
 pub mod graphite;
+pub mod kanon;
+pub mod mapping;
 pub mod obf;
+pub mod pii;
 pub mod prometheus;
+pub mod rewrite;
 pub mod server;
+pub mod template;
+pub mod transform;
@@ -0,0 +1,95 @@
+use super::{RARE, Strategy, whole};
+use regex::Regex;
+use std::fmt;
+
+/*
+Output leak guard, the last check before metric is written.
+
+Every part of obfuscated path has to be either a token of one of
+the configured strategies, or an explicitly allowed cleartext:
+name segments and tag values matching their patterns, tag keys
+matching their pattern. Patterns match the whole part, the rare
+values bucket is always allowed.
+*/
+pub struct Guard {
+    strategies: Vec<Strategy>,
+    segments: Option<Regex>,
+    tag_keys: Option<Regex>,
+    tag_values: Option<Regex>,
+}
+
+// part of path which is neither a token nor an allowed cleartext
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Leak {
+    Segment(String),
+    TagKey(String),
+    TagValue(String),
+}
+
+impl Leak {
+    // kind of leaked part, safe to log
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Leak::Segment(_) => "segment",
+            Leak::TagKey(_) => "tag key",
+            Leak::TagValue(_) => "tag value",
+        }
+    }
+}
+
+impl fmt::Display for Leak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Leak::Segment(part) | Leak::TagKey(part) | Leak::TagValue(part) => {
+                write!(f, "cleartext {}: {}", self.kind(), part)
+            }
+        }
+    }
+}
+
+impl Guard {
+    pub fn new(
+        strategies: Vec<Strategy>,
+        segments: Option<&str>,
+        tag_keys: Option<&str>,
+        tag_values: Option<&str>,
+    ) -> Result<Self, String> {
+        let whole =
+            |pattern| whole(pattern).map_err(|e| format!("invalid leak guard pattern: {}", e));
+
+        Ok(Self {
+            strategies,
+            segments: whole(segments)?,
+            tag_keys: whole(tag_keys)?,
+            tag_values: whole(tag_values)?,
+        })
+    }
+
+    fn allowed(&self, part: &str, cleartext: &Option<Regex>) -> bool {
+        part == RARE
+            || self.strategies.iter().any(|s| s.is_token(part))
+            || cleartext.as_ref().is_some_and(|re| re.is_match(part))
+    }
+
+    pub fn check(&self, path: &str) -> Result<(), Leak> {
+        let mut parts = path.split(';');
+
+        for segment in parts.next().unwrap_or_default().split('.') {
+            if !self.allowed(segment, &self.segments) {
+                return Err(Leak::Segment(segment.to_string()));
+            }
+        }
+
+        for tag in parts {
+            let (key, value) = tag.split_once('=').unwrap_or((tag, ""));
+            if !self.allowed(key, &self.tag_keys) {
+                return Err(Leak::TagKey(key.to_string()));
+            }
+            if !self.allowed(value, &self.tag_values) {
+                return Err(Leak::TagValue(value.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
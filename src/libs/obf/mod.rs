@@ -1,59 +1,545 @@
+mod guard;
+mod routes;
+mod strategy;
+
+use crate::libs::config::ObfConfig;
 use crate::libs::graphite::GraphiteMetric;
-use ahash::AHasher;
-use std::hash::{Hash, Hasher};
+use regex::Regex;
+use std::fmt;
 
-// maximum resulted metric length
-// name + 16 tags * 32 bytes
-pub const MAX_METRIC_LEN: usize = 512;
+pub use guard::{Guard, Leak};
+pub use routes::{Route, Routes};
+pub use strategy::{Encoding, Format, Strategy};
 
-#[inline(always)]
-fn fast_hash(input: &str) -> u64 {
-    let mut h = AHasher::default();
-    input.hash(&mut h);
-    h.finish()
+// shared bucket of rare tag values, kept as is, see kanon module
+pub const RARE: &str = "obf_rare";
+
+// obfuscated token: prefix + 16 hex digits by default
+const TOKEN_LEN: usize = 5 + 16;
+
+// metric limits, metrics above them are rejected
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub max_name_len: usize,
+    pub max_tags: usize,
+    pub max_tag_key_len: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_name_len: 1024,
+            max_tags: 64,
+            max_tag_key_len: 128,
+        }
+    }
+}
+
+// parts of metrics passed through unhashed
+#[derive(Debug, Clone, Default)]
+pub struct Cleartext {
+    // tag keys which values are kept
+    pub tag_keys: Vec<String>,
+    // tag values matching the pattern are kept
+    pub tag_values: Option<Regex>,
+    // positions of name segments which are kept, starting from 0
+    pub segments: Vec<usize>,
+}
+
+impl Cleartext {
+    fn tag_value(&self, key: &str, value: &str) -> bool {
+        self.tag_keys.iter().any(|k| k == key)
+            || self
+                .tag_values
+                .as_ref()
+                .is_some_and(|re| re.is_match(value))
+    }
+}
+
+// previous key kept during rotation, points are written with both keys
+// until the end of transition window
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    pub strategy: Strategy,
+    pub strategies: Strategies,
+    // end of transition window, unix timestamp
+    pub until: i64,
+}
+
+// keyed and encrypting strategies of the same key, selectable per
+// route, unset without a key
+#[derive(Debug, Clone, Default)]
+pub struct Strategies {
+    pub keyed: Option<Strategy>,
+    pub encrypted: Option<Strategy>,
+}
+
+impl Strategies {
+    // both strategies, the configured one is used as is, the other one
+    // keeps its default prefix, so tokens of both stay distinguishable
+    fn new(strategy: &Strategy, secret: Option<&[u8]>, version: u32, format: &Format) -> Self {
+        let Some(secret) = secret else {
+            return Self::default();
+        };
+        let other = Format {
+            prefix: None,
+            ..format.clone()
+        };
+
+        match strategy {
+            Strategy::Encrypted { .. } => Self {
+                keyed: Some(Strategy::keyed(secret, version).with_format(&other)),
+                encrypted: Some(strategy.clone()),
+            },
+            _ => Self {
+                keyed: Some(strategy.clone()),
+                encrypted: Some(Strategy::encrypted(secret, version).with_format(&other)),
+            },
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Strategy> {
+        self.keyed.iter().chain(self.encrypted.iter())
+    }
+}
+
+// obfuscation options
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub limits: Limits,
+    // sort and de-duplicate tags before hashing
+    pub canonical_tags: bool,
+    pub strategy: Strategy,
+    // hash each dot-separated name segment on its own
+    pub segments: bool,
+    pub cleartext: Cleartext,
+    // tag keys which are obfuscated too, `*` for every key
+    pub hash_tag_keys: Vec<String>,
+    // strategies selectable per route, see obf::Routes
+    pub strategies: Strategies,
+    pub rotation: Option<Rotation>,
+}
+
+// anchor pattern, so it matches the whole value and not a part of it
+fn whole(pattern: Option<&str>) -> Result<Option<Regex>, regex::Error> {
+    pattern
+        .map(|p| Regex::new(&format!("^(?:{})$", p)))
+        .transpose()
+}
+
+// read secret inline or from file, trailing whitespaces are trimmed
+fn secret(key: &Option<String>, file: &Option<String>) -> Result<Option<Vec<u8>>, String> {
+    let secret = match (key, file) {
+        (Some(key), _) => key.clone().into_bytes(),
+        (None, Some(path)) => {
+            let key = std::fs::read(path)
+                .map_err(|e| format!("unable to read obfuscation key {}: {}", path, e))?;
+            key.trim_ascii_end().to_vec()
+        }
+        (None, None) => return Ok(None),
+    };
+
+    // an empty key would silently hash with a well-known secret
+    if secret.is_empty() {
+        return Err("obfuscation key is empty".to_string());
+    }
+    Ok(Some(secret))
+}
+
+// version 0 is the legacy unkeyed hash, keys start with 1
+fn load_strategy(mode: &str, secret: Option<Vec<u8>>, version: u32) -> Result<Strategy, String> {
+    if secret.is_some() && version == 0 {
+        return Err("key version 0 is reserved for legacy tokens".to_string());
+    }
+    match (mode, secret) {
+        ("hash", Some(secret)) => Ok(Strategy::keyed(&secret, version)),
+        ("hash", None) => Ok(Strategy::Legacy),
+        ("encrypt", Some(secret)) => Ok(Strategy::encrypted(&secret, version)),
+        ("encrypt", None) => Err("encrypt mode requires a key".to_string()),
+        (mode, _) => Err(format!("unknown obfuscation mode: {}", mode)),
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            limits: Limits::default(),
+            canonical_tags: true,
+            strategy: Strategy::default(),
+            segments: false,
+            cleartext: Cleartext::default(),
+            hash_tag_keys: Vec::new(),
+            strategies: Strategies::default(),
+            rotation: None,
+        }
+    }
+}
+
+impl Options {
+    // cleartext keys are never obfuscated
+    fn hash_tag_key(&self, key: &str) -> bool {
+        !self.hash_tag_keys.is_empty()
+            && self.hash_tag_keys.iter().any(|k| k == "*" || k == key)
+            && !self.cleartext.tag_keys.iter().any(|k| k == key)
+    }
+
+    // options of the previous key, if any
+    pub fn previous(&self) -> Option<Self> {
+        let rotation = self.rotation.as_ref()?;
+        Some(Self {
+            strategy: rotation.strategy.clone(),
+            strategies: rotation.strategies.clone(),
+            rotation: None,
+            ..self.clone()
+        })
+    }
+
+    // transition window is open at `now`, unix timestamp
+    pub fn rotating(&self, now: i64) -> bool {
+        self.rotation.as_ref().is_some_and(|r| now < r.until)
+    }
+
+    // build options from configuration, reads the key files if any
+    pub fn load(config: &ObfConfig) -> Result<Self, String> {
+        let format = Format {
+            prefix: config.obf_prefix.clone(),
+            len: config.obf_hash_len.into(),
+            encoding: config.obf_encoding.parse()?,
+        };
+        format.check()?;
+
+        let key = secret(&config.obf_key, &config.obf_key_file)?;
+        let strategy = load_strategy(&config.obf_mode, key.clone(), config.obf_key_version)?;
+        if let Strategy::Legacy = strategy
+            && format != Format::default()
+        {
+            return Err("token format requires a key".to_string());
+        }
+        let strategy = strategy.with_format(&format);
+        let strategies =
+            Strategies::new(&strategy, key.as_deref(), config.obf_key_version, &format);
+
+        // previous key without secret is the legacy unkeyed hash
+        let rotation = match config.obf_rotation_until {
+            Some(until) => {
+                let prev_key = secret(&config.obf_prev_key, &config.obf_prev_key_file)?;
+                let version = config
+                    .obf_prev_key_version
+                    .unwrap_or(config.obf_key_version.saturating_sub(1));
+                let previous = load_strategy(
+                    config.obf_prev_mode.as_deref().unwrap_or(&config.obf_mode),
+                    prev_key.clone(),
+                    version,
+                )?
+                .with_format(&format);
+                if previous.prefix() == strategy.prefix() {
+                    return Err(format!(
+                        "previous key has the same token prefix: {}",
+                        previous.prefix()
+                    ));
+                }
+                Some(Rotation {
+                    strategies: Strategies::new(&previous, prev_key.as_deref(), version, &format),
+                    strategy: previous,
+                    until,
+                })
+            }
+            None => None,
+        };
+
+        let tag_values = whole(config.clear_tag_values.as_deref())
+            .map_err(|e| format!("invalid cleartext tag values pattern: {}", e))?;
+
+        Ok(Self {
+            limits: Limits {
+                max_name_len: config.max_name_len.into(),
+                max_tags: config.max_tags.into(),
+                max_tag_key_len: config.max_tag_key_len.into(),
+            },
+            canonical_tags: config.canonical_tags,
+            strategy,
+            segments: config.obf_segments,
+            cleartext: Cleartext {
+                tag_keys: config.clear_tag_keys.clone(),
+                tag_values,
+                segments: config.clear_segments.clone(),
+            },
+            hash_tag_keys: config.hash_tag_keys.clone(),
+            strategies,
+            rotation,
+        })
+    }
 }
 
-// write u64 hex directly into buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObfError {
+    NameTooLong { len: usize, max: usize },
+    TooManyTags { count: usize, max: usize },
+    TagKeyTooLong { key: String, max: usize },
+    InvalidToken { token: String },
+}
+
+impl fmt::Display for ObfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObfError::NameTooLong { len, max } => {
+                write!(f, "metric name too long: {} > {}", len, max)
+            }
+            ObfError::TooManyTags { count, max } => {
+                write!(f, "too many tags: {} > {}", count, max)
+            }
+            ObfError::TagKeyTooLong { key, max } => {
+                write!(f, "tag key too long: '{}' > {}", key, max)
+            }
+            ObfError::InvalidToken { token } => {
+                write!(f, "unable to decrypt token: {}", token)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObfError {}
+
+/*
+Obfuscator turns parsed metric into the stored path, `on_token` is
+called with every (cleartext, token) pair. `Options` is the built-in
+hashing and encrypting obfuscator, implement it to plug in your own.
+*/
+pub trait Obfuscator: Send + Sync {
+    fn obfuscate_with(
+        &self,
+        metric: &GraphiteMetric,
+        on_token: &mut dyn FnMut(&str, &str),
+    ) -> Result<String, ObfError>;
+
+    fn obfuscate(&self, metric: &GraphiteMetric) -> Result<String, ObfError> {
+        self.obfuscate_with(metric, &mut |_, _| {})
+    }
+}
+
+impl Obfuscator for Options {
+    fn obfuscate_with(
+        &self,
+        metric: &GraphiteMetric,
+        on_token: &mut dyn FnMut(&str, &str),
+    ) -> Result<String, ObfError> {
+        obfuscate_with(metric, self, on_token)
+    }
+}
+
+// stores metrics in cleartext, for non-sensitive namespaces only
+#[derive(Debug, Clone, Default)]
+pub struct Passthrough;
+
+impl Obfuscator for Passthrough {
+    fn obfuscate_with(
+        &self,
+        metric: &GraphiteMetric,
+        _on_token: &mut dyn FnMut(&str, &str),
+    ) -> Result<String, ObfError> {
+        Ok(crate::libs::filter::path(metric))
+    }
+}
+
+// check metric against limits before any work is done
+fn check(metric: &GraphiteMetric, limits: &Limits) -> Result<(), ObfError> {
+    if metric.name.len() > limits.max_name_len {
+        return Err(ObfError::NameTooLong {
+            len: metric.name.len(),
+            max: limits.max_name_len,
+        });
+    }
+    if metric.tags.len() > limits.max_tags {
+        return Err(ObfError::TooManyTags {
+            count: metric.tags.len(),
+            max: limits.max_tags,
+        });
+    }
+    if let Some((key, _)) = metric
+        .tags
+        .iter()
+        .find(|(key, _)| key.len() > limits.max_tag_key_len)
+    {
+        return Err(ObfError::TagKeyTooLong {
+            key: key.to_string(),
+            max: limits.max_tag_key_len,
+        });
+    }
+    Ok(())
+}
+
+// write token of input and report (cleartext, token) pair
 #[inline(always)]
-fn write_hex(mut n: u64, buf: &mut [u8], pos: &mut usize) {
-    const HEX: &[u8; 16] = b"0123456789abcdef";
-    assert!(*pos + 16 <= buf.len(), "buffer overflow in write_hex");
-    for i in (0..16).rev() {
-        buf[*pos + i] = HEX[(n & 0xF) as usize];
-        n >>= 4;
+fn write_token<F>(input: &str, options: &Options, buf: &mut String, on_token: &mut F)
+where
+    F: FnMut(&str, &str),
+{
+    let start = buf.len();
+    options.strategy.write_token(input, buf);
+    on_token(input, &buf[start..]);
+}
+
+// write obfuscated name, cleartext segments are kept and the runs of
+// hidden segments between them are single tokens, unless every segment
+// has to be obfuscated on its own
+fn write_name<F>(name: &str, options: &Options, buf: &mut String, on_token: &mut F)
+where
+    F: FnMut(&str, &str),
+{
+    let clear = &options.cleartext.segments;
+    if clear.is_empty() && !options.segments {
+        write_token(name, options, buf, on_token);
+        return;
+    }
+
+    let start = buf.len();
+    let separator = |buf: &mut String| {
+        if buf.len() > start {
+            buf.push('.');
+        }
+    };
+
+    // byte range of hidden segments run
+    let mut run: Option<(usize, usize)> = None;
+    let mut offset = 0;
+
+    for (i, segment) in name.split('.').enumerate() {
+        let range = (offset, offset + segment.len());
+        offset = range.1 + 1;
+
+        if clear.contains(&i) {
+            if let Some((from, to)) = run.take() {
+                separator(buf);
+                write_token(&name[from..to], options, buf, on_token);
+            }
+            separator(buf);
+            buf.push_str(segment);
+        } else if options.segments {
+            separator(buf);
+            write_token(segment, options, buf, on_token);
+        } else {
+            run = Some(run.map_or(range, |(from, _)| (from, range.1)));
+        }
+    }
+
+    if let Some((from, to)) = run {
+        separator(buf);
+        write_token(&name[from..to], options, buf, on_token);
     }
-    *pos += 16;
 }
 
-// obfuscate one metric and write into buffer
-pub fn obfuscate<'a>(metric: &GraphiteMetric, buf: &'a mut [u8; MAX_METRIC_LEN]) -> &'a str {
-    let mut pos = 0;
+// obfuscate one metric into a new path
+pub fn obfuscate(metric: &GraphiteMetric, options: &Options) -> Result<String, ObfError> {
+    obfuscate_with(metric, options, |_, _| {})
+}
 
-    // name
-    assert!(pos + 4 + 16 <= MAX_METRIC_LEN, "buffer overflow at name");
-    buf[pos..pos + 4].copy_from_slice(b"obf_");
-    pos += 4;
-    write_hex(fast_hash(metric.name), buf, &mut pos);
+// obfuscate one metric, `on_token` is called with every (cleartext, token) pair
+pub fn obfuscate_with<F>(
+    metric: &GraphiteMetric,
+    options: &Options,
+    mut on_token: F,
+) -> Result<String, ObfError>
+where
+    F: FnMut(&str, &str),
+{
+    let canonical;
+    let metric = if options.canonical_tags {
+        canonical = {
+            let mut m = metric.clone();
+            m.canonicalize();
+            m
+        };
+        &canonical
+    } else {
+        metric
+    };
+
+    check(metric, &options.limits)?;
+
+    let name_tokens = match options.segments {
+        true => metric.name.split('.').count(),
+        false => 1,
+    };
+    let keys_len: usize = metric.tags.iter().map(|(key, _)| key.len() + 2).sum();
+    let mut buf = String::with_capacity(TOKEN_LEN * (name_tokens + metric.tags.len()) + keys_len);
+
+    // name, per segment keeps graphite hierarchy
+    write_name(metric.name, options, &mut buf, &mut on_token);
 
     // tags
+    let tags_start = buf.len();
+    let mut hashed_keys = false;
+
     for (key, value) in &metric.tags {
-        assert!(
-            pos + 1 + key.len() + 1 + 4 + 16 <= MAX_METRIC_LEN,
-            "buffer overflow at tag: key='{}', pos={}",
-            key,
-            pos
-        );
-        buf[pos] = b';';
-        pos += 1;
-        buf[pos..pos + key.len()].copy_from_slice(key.as_bytes());
-        pos += key.len();
-        buf[pos] = b'=';
-        pos += 1;
-        buf[pos..pos + 4].copy_from_slice(b"obf_");
-        pos += 4;
-        write_hex(fast_hash(value), buf, &mut pos);
-    }
-
-    // safe transform data from buffer to &str
-    std::str::from_utf8(&buf[..pos]).unwrap()
+        buf.push(';');
+        if options.hash_tag_key(key) {
+            write_token(key, options, &mut buf, &mut on_token);
+            hashed_keys = true;
+        } else {
+            buf.push_str(key);
+        }
+        buf.push('=');
+        if *value == RARE || options.cleartext.tag_value(key, value) {
+            buf.push_str(value);
+        } else {
+            write_token(value, options, &mut buf, &mut on_token);
+        }
+    }
+
+    // keep canonical order of the stored path when keys are hashed
+    if options.canonical_tags && hashed_keys {
+        let mut tags: Vec<&str> = buf[tags_start + 1..].split(';').collect();
+        tags.sort_by_key(|tag| tag.split_once('=').map_or(*tag, |(key, _)| key));
+        let sorted = tags.join(";");
+
+        buf.truncate(tags_start + 1);
+        buf.push_str(&sorted);
+    }
+
+    Ok(buf)
+}
+
+// decrypt every token of obfuscated path, cleartext parts are kept
+pub fn decrypt(path: &str, strategy: &Strategy) -> Result<String, ObfError> {
+    // cleartext parts, even with the token prefix, are passed through
+    let decrypt = |token: &str, buf: &mut String| {
+        if !strategy.is_token(token) {
+            buf.push_str(token);
+            return Ok(());
+        }
+        let clear = strategy
+            .decrypt(token)
+            .ok_or_else(|| ObfError::InvalidToken {
+                token: token.to_string(),
+            })?;
+        buf.push_str(&clear);
+        Ok(())
+    };
+
+    let mut parts = path.split(';');
+    let mut buf = String::with_capacity(path.len());
+
+    for (i, segment) in parts.next().unwrap_or_default().split('.').enumerate() {
+        if i > 0 {
+            buf.push('.');
+        }
+        decrypt(segment, &mut buf)?;
+    }
+
+    for tag in parts {
+        buf.push(';');
+        match tag.split_once('=') {
+            Some((key, value)) => {
+                decrypt(key, &mut buf)?;
+                buf.push('=');
+                decrypt(value, &mut buf)?;
+            }
+            None => buf.push_str(tag),
+        }
+    }
+
+    Ok(buf)
 }
+
+#[cfg(test)]
+mod tests;
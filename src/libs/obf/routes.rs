@@ -0,0 +1,129 @@
+// Obfuscator per routing rule, one rule per line:
+//
+//     <selector> <obfuscator>
+//
+//     glob:public.* passthrough
+//     glob:servers.* segments
+//     ^billing\. full
+//
+// Selector is an allow/block list rule (regex or `glob:`), see filter
+// module. The first matching rule wins, metrics without a match use the
+// default obfuscator. Built-in obfuscators are derived from configured
+// options:
+//
+// - `default`: configured options as is
+// - `full`: the whole name is a single token
+// - `segments`: each name segment is a token
+// - `keyed`: configured options with keyed hashing, requires a key
+// - `encrypted`: configured options with encryption, requires a key
+// - `passthrough`: metric is stored in cleartext
+use super::{ObfError, Obfuscator, Options, Passthrough, Strategy};
+use crate::libs::filter;
+use crate::libs::graphite::GraphiteMetric;
+
+pub struct Route {
+    pub pattern: String,
+    selector: filter::Rule,
+    obfuscator: Box<dyn Obfuscator>,
+}
+
+pub struct Routes {
+    routes: Vec<Route>,
+    default: Box<dyn Obfuscator>,
+}
+
+impl Routes {
+    pub fn new(default: Box<dyn Obfuscator>) -> Self {
+        Self {
+            routes: Vec::new(),
+            default,
+        }
+    }
+
+    // add a route, checked after the already added ones
+    pub fn route(&mut self, selector: &str, obfuscator: Box<dyn Obfuscator>) -> Result<(), String> {
+        self.routes.push(Route {
+            pattern: selector.to_string(),
+            selector: filter::Rule::new(selector)?,
+            obfuscator,
+        });
+        Ok(())
+    }
+
+    // parse rules, empty lines and `#` comments are skipped
+    pub fn parse(content: &str, options: &Options) -> Result<Self, String> {
+        let mut routes = Self::new(Box::new(options.clone()));
+
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [selector, name] = fields.as_slice() else {
+                return Err(format!("line {}: expected `selector obfuscator`", n + 1));
+            };
+            let with = |strategy: &Option<Strategy>| -> Result<Box<dyn Obfuscator>, String> {
+                let strategy = strategy
+                    .clone()
+                    .ok_or_else(|| format!("line {}: {} obfuscator requires a key", n + 1, name))?;
+                Ok(Box::new(Options {
+                    strategy,
+                    ..options.clone()
+                }))
+            };
+            let obfuscator: Box<dyn Obfuscator> = match *name {
+                "default" => Box::new(options.clone()),
+                "full" => Box::new(Options {
+                    segments: false,
+                    ..options.clone()
+                }),
+                "segments" => Box::new(Options {
+                    segments: true,
+                    ..options.clone()
+                }),
+                "keyed" => with(&options.strategies.keyed)?,
+                "encrypted" => with(&options.strategies.encrypted)?,
+                "passthrough" => Box::new(Passthrough),
+                other => return Err(format!("line {}: unknown obfuscator {:?}", n + 1, other)),
+            };
+            routes
+                .route(selector, obfuscator)
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+        }
+
+        Ok(routes)
+    }
+
+    pub fn load(path: &str, options: &Options) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read obfuscation routes {}: {}", path, e))?;
+        Self::parse(&content, options)
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+}
+
+impl Obfuscator for Routes {
+    fn obfuscate_with(
+        &self,
+        metric: &GraphiteMetric,
+        on_token: &mut dyn FnMut(&str, &str),
+    ) -> Result<String, ObfError> {
+        if self.routes.is_empty() {
+            return self.default.obfuscate_with(metric, on_token);
+        }
+
+        let path = filter::path(metric);
+        let obfuscator = self
+            .routes
+            .iter()
+            .find(|route| route.selector.matches(metric, &path))
+            .map_or(&self.default, |route| &route.obfuscator);
+
+        obfuscator.obfuscate_with(metric, on_token)
+    }
+}
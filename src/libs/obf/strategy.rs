@@ -0,0 +1,349 @@
+use aes_siv::KeyInit;
+use aes_siv::siv::Aes256Siv;
+use ahash::AHasher;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+// key derivation contexts, changing them changes every token
+const KEY_CONTEXT: &str = "sleipnir 2025-10 obfuscation key v1";
+const ENCRYPTION_KEY_CONTEXT: &str = "sleipnir 2025-10 encryption key v1";
+
+// AES-SIV instances per encrypting strategy
+const CIPHERS: usize = 16;
+
+/*
+Strategy of obfuscated tokens.
+
+Keyed tokens are stable across versions and platforms:
+
+    token = "obf" + version + "_" + hex(BLAKE3-keyed(key, value)[..8])
+    key   = BLAKE3-derive-key(KEY_CONTEXT, secret)
+
+prefix, hash length and encoding (hex or base32) could be changed
+with `Format`, defaults are shown above.
+
+Encrypted tokens are reversible with the same secret:
+
+    token = "enc" + version + "_" + base64url(AES-SIV(key, value))
+    key   = BLAKE3-derive-key(ENCRYPTION_KEY_CONTEXT, secret)[..64]
+
+where value is UTF-8 name or tag value, hex is lowercase,
+AES-SIV is AES-CMAC-SIV with 512 bits key and no associated
+data, base64url is URL-safe alphabet without padding.
+*/
+// encoding of keyed hashes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    // lowercase hex
+    #[default]
+    Hex,
+    // lowercase RFC 4648 alphabet without padding
+    Base32,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(Encoding::Hex),
+            "base32" => Ok(Encoding::Base32),
+            other => Err(format!("unknown token encoding: {}", other)),
+        }
+    }
+}
+
+// token format, legacy tokens are never changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    // prefix template, `{version}` is replaced with key version
+    pub prefix: Option<String>,
+    // hash length of keyed tokens in bytes, 4..=32
+    pub len: usize,
+    pub encoding: Encoding,
+}
+
+impl Format {
+    // prefix can't contain path separators, tokens would break the path
+    // structure and couldn't be recognized or decrypted back
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(prefix) = &self.prefix
+            && (prefix.is_empty()
+                || prefix.contains(|c: char| matches!(c, '.' | ';' | '=') || c.is_whitespace()))
+        {
+            return Err(format!("invalid token prefix: {:?}", prefix));
+        }
+        if !(4..=32).contains(&self.len) {
+            return Err(format!("hash length has to be 4..=32: {}", self.len));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self {
+            prefix: None,
+            len: 8,
+            encoding: Encoding::Hex,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub enum Strategy {
+    // unkeyed ahash with `obf_` prefix, output may change between
+    // ahash versions and platforms, kept for compatibility
+    #[default]
+    Legacy,
+    Keyed {
+        key: [u8; 32],
+        version: u32,
+        prefix: String,
+        len: usize,
+        encoding: Encoding,
+    },
+    Encrypted {
+        cipher: Cipher,
+        version: u32,
+        prefix: String,
+    },
+}
+
+// AES-SIV instances set up once, encryption needs exclusive access, so
+// concurrent workers take the first free one
+#[derive(Clone)]
+pub struct Cipher(Arc<[Mutex<Aes256Siv>]>);
+
+impl Cipher {
+    fn new(key: &[u8; 64]) -> Self {
+        Self(
+            (0..CIPHERS)
+                .map(|_| Mutex::new(Aes256Siv::new_from_slice(key).unwrap()))
+                .collect(),
+        )
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut Aes256Siv) -> T) -> T {
+        for siv in self.0.iter() {
+            if let Ok(mut siv) = siv.try_lock() {
+                return f(&mut siv);
+            }
+        }
+        f(&mut self.0[0].lock().unwrap())
+    }
+}
+
+#[inline(always)]
+fn fast_hash(input: &str) -> u64 {
+    let mut h = AHasher::default();
+    input.hash(&mut h);
+    h.finish()
+}
+
+// write bytes as lowercase hex directly into buffer
+#[inline(always)]
+fn write_hex(bytes: &[u8], buf: &mut String) {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    for &byte in bytes {
+        buf.push(HEX[(byte >> 4) as usize] as char);
+        buf.push(HEX[(byte & 0xF) as usize] as char);
+    }
+}
+
+// write bytes as lowercase base32 without padding
+fn write_base32(bytes: &[u8], buf: &mut String) {
+    const BASE32: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let (mut acc, mut bits) = (0u32, 0);
+    for &byte in bytes {
+        acc = (acc << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            buf.push(BASE32[(acc >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        buf.push(BASE32[(acc << (5 - bits)) as usize & 31] as char);
+    }
+}
+
+impl Strategy {
+    pub fn keyed(secret: &[u8], version: u32) -> Self {
+        Strategy::Keyed {
+            key: blake3::derive_key(KEY_CONTEXT, secret),
+            version,
+            prefix: format!("obf{}_", version),
+            len: 8,
+            encoding: Encoding::Hex,
+        }
+    }
+
+    pub fn encrypted(secret: &[u8], version: u32) -> Self {
+        let mut key = [0u8; 64];
+        blake3::Hasher::new_derive_key(ENCRYPTION_KEY_CONTEXT)
+            .update(secret)
+            .finalize_xof()
+            .fill(&mut key);
+
+        Strategy::Encrypted {
+            cipher: Cipher::new(&key),
+            version,
+            prefix: format!("enc{}_", version),
+        }
+    }
+
+    // change token format, hash length is clamped to 4..=32 bytes
+    pub fn with_format(mut self, format: &Format) -> Self {
+        match &mut self {
+            Strategy::Legacy => {}
+            Strategy::Keyed {
+                version,
+                prefix,
+                len,
+                encoding,
+                ..
+            } => {
+                if let Some(template) = &format.prefix {
+                    *prefix = template.replace("{version}", &version.to_string());
+                }
+                *len = format.len.clamp(4, 32);
+                *encoding = format.encoding;
+            }
+            Strategy::Encrypted {
+                version, prefix, ..
+            } => {
+                if let Some(template) = &format.prefix {
+                    *prefix = template.replace("{version}", &version.to_string());
+                }
+            }
+        }
+        self
+    }
+
+    // token prefix, carries algorithm and key version
+    pub fn prefix(&self) -> &str {
+        match self {
+            Strategy::Legacy => "obf_",
+            Strategy::Keyed { prefix, .. } | Strategy::Encrypted { prefix, .. } => prefix,
+        }
+    }
+
+    // key version, legacy hash has none
+    pub fn version(&self) -> u32 {
+        match self {
+            Strategy::Legacy => 0,
+            Strategy::Keyed { version, .. } | Strategy::Encrypted { version, .. } => *version,
+        }
+    }
+
+    // token of input
+    pub fn token(&self, input: &str) -> String {
+        let mut buf = String::new();
+        self.write_token(input, &mut buf);
+        buf
+    }
+
+    // write token of input into buffer
+    #[inline(always)]
+    pub(super) fn write_token(&self, input: &str, buf: &mut String) {
+        buf.push_str(self.prefix());
+
+        match self {
+            Strategy::Legacy => write_hex(&fast_hash(input).to_be_bytes(), buf),
+            Strategy::Keyed {
+                key, len, encoding, ..
+            } => {
+                let hash = blake3::keyed_hash(key, input.as_bytes());
+                let hash = &hash.as_bytes()[..*len];
+                match encoding {
+                    Encoding::Hex => write_hex(hash, buf),
+                    Encoding::Base32 => write_base32(hash, buf),
+                }
+            }
+            Strategy::Encrypted { cipher, .. } => {
+                // encryption fails only with too many headers
+                let encrypted = cipher
+                    .with(|siv| siv.encrypt(std::iter::empty::<&[u8]>(), input.as_bytes()))
+                    .unwrap();
+                URL_SAFE_NO_PAD.encode_string(encrypted, buf);
+            }
+        }
+    }
+
+    // whether the whole string is a token of this strategy
+    pub fn is_token(&self, token: &str) -> bool {
+        let Some(encoded) = token.strip_prefix(self.prefix()) else {
+            return false;
+        };
+        let hex = |b: u8| b.is_ascii_digit() || (b'a'..=b'f').contains(&b);
+
+        match self {
+            Strategy::Legacy => encoded.len() == 16 && encoded.bytes().all(hex),
+            Strategy::Keyed {
+                len,
+                encoding: Encoding::Hex,
+                ..
+            } => encoded.len() == len * 2 && encoded.bytes().all(hex),
+            Strategy::Keyed {
+                len,
+                encoding: Encoding::Base32,
+                ..
+            } => {
+                encoded.len() == (len * 8).div_ceil(5)
+                    && encoded
+                        .bytes()
+                        .all(|b| b.is_ascii_lowercase() || (b'2'..=b'7').contains(&b))
+            }
+            // at least 128 bits synthetic IV
+            Strategy::Encrypted { .. } => {
+                encoded.len() >= 22
+                    && encoded
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+            }
+        }
+    }
+
+    // decrypt one token, `None` for foreign or tampered tokens
+    pub fn decrypt(&self, token: &str) -> Option<String> {
+        let Strategy::Encrypted { cipher, prefix, .. } = self else {
+            return None;
+        };
+
+        let encrypted = URL_SAFE_NO_PAD
+            .decode(token.strip_prefix(prefix.as_str())?)
+            .ok()?;
+        let decrypted = cipher
+            .with(|siv| siv.decrypt(std::iter::empty::<&[u8]>(), &encrypted))
+            .ok()?;
+
+        String::from_utf8(decrypted).ok()
+    }
+}
+
+// never print the key
+impl fmt::Debug for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Strategy::Legacy => write!(f, "Legacy"),
+            Strategy::Keyed {
+                version,
+                len,
+                encoding,
+                ..
+            } => write!(
+                f,
+                "Keyed {{ version: {}, len: {}, encoding: {:?} }}",
+                version, len, encoding
+            ),
+            Strategy::Encrypted { version, .. } => {
+                write!(f, "Encrypted {{ version: {} }}", version)
+            }
+        }
+    }
+}
@@ -8,7 +8,7 @@ use crate::libs::graphite::GraphiteMetric;
 fn test_obf_name_changed() {
     let metric = GraphiteMetric::parse("cpu.usage 42.5 1234567890").unwrap();
 
-    let (name, _, _) = obfuscate(&metric);
+    let name = obfuscate(&metric, &Options::default()).unwrap();
 
     assert!(name.starts_with("obf_"));
     assert!(!name.contains("cpu.usage"));
@@ -19,8 +19,8 @@ fn test_obf_name_changed() {
 fn test_obf_is_continues() {
     let metric = GraphiteMetric::parse("memory.used 1024.0 1234567890").unwrap();
 
-    let obfuscated1 = obf_to_string(obfuscate(&metric));
-    let obfuscated2 = obf_to_string(obfuscate(&metric));
+    let obfuscated1 = obfuscate(&metric, &Options::default()).unwrap();
+    let obfuscated2 = obfuscate(&metric, &Options::default()).unwrap();
 
     assert_eq!(obfuscated1, obfuscated2);
 }
@@ -31,10 +31,620 @@ fn test_obf_with_tags() {
         GraphiteMetric::parse("app.requests;host=server01;region=eu-west 150.0 1234567890")
             .unwrap();
 
-    let (name, _, _) = obfuscate(&metric);
+    let name = obfuscate(&metric, &Options::default()).unwrap();
 
     assert!(name.contains(";host=obf_"));
     assert!(name.contains(";region=obf_"));
     assert!(!name.contains("server01"));
     assert!(!name.contains("eu-west"));
 }
+
+#[test]
+fn test_obf_long_metric_no_panic() {
+    let tags: String = (0..40)
+        .map(|i| format!(";{}={}", "k".repeat(60) + &i.to_string(), i))
+        .collect();
+    let line = format!("app.requests{} 1.0 1234567890", tags);
+    let metric = GraphiteMetric::parse(&line).unwrap();
+
+    let name = obfuscate(&metric, &Options::default()).unwrap();
+
+    assert_eq!(name.matches("=obf_").count(), 40);
+}
+
+#[test]
+fn test_obf_limits_rejected() {
+    let options = Options {
+        limits: Limits {
+            max_name_len: 8,
+            max_tags: 1,
+            max_tag_key_len: 4,
+        },
+        ..Options::default()
+    };
+
+    let metric = GraphiteMetric::parse("app.requests 1.0 1234567890").unwrap();
+    assert_eq!(
+        obfuscate(&metric, &options),
+        Err(ObfError::NameTooLong { len: 12, max: 8 })
+    );
+
+    let metric = GraphiteMetric::parse("app;a=1;b=2 1.0 1234567890").unwrap();
+    assert_eq!(
+        obfuscate(&metric, &options),
+        Err(ObfError::TooManyTags { count: 2, max: 1 })
+    );
+
+    let metric = GraphiteMetric::parse("app;region=1 1.0 1234567890").unwrap();
+    assert_eq!(
+        obfuscate(&metric, &options),
+        Err(ObfError::TagKeyTooLong {
+            key: "region".to_string(),
+            max: 4
+        })
+    );
+}
+
+#[test]
+fn test_obf_canonical_tags() {
+    let a = GraphiteMetric::parse("app;x=1;y=2 1.0 1234567890").unwrap();
+    let b = GraphiteMetric::parse("app;y=0;y=2;x=1 1.0 1234567890").unwrap();
+
+    assert_eq!(
+        obfuscate(&a, &Options::default()),
+        obfuscate(&b, &Options::default())
+    );
+
+    let keep_order = Options {
+        canonical_tags: false,
+        ..Options::default()
+    };
+    let name = obfuscate(&b, &keep_order).unwrap();
+    assert_eq!(name.matches(";y=").count(), 2);
+    assert!(name.find(";y=") < name.find(";x="));
+}
+
+#[test]
+fn test_obf_keyed_stable() {
+    let options = Options {
+        strategy: Strategy::keyed(b"secret", 1),
+        ..Options::default()
+    };
+    let metric = GraphiteMetric::parse("cpu.usage;host=server01 42.5 1234567890").unwrap();
+
+    let name = obfuscate(&metric, &options).unwrap();
+
+    assert_eq!(name, "obf1_bd13cac033a57ba5;host=obf1_fa556ee314fd809b");
+}
+
+#[test]
+fn test_obf_keyed_depends_on_key() {
+    let metric = GraphiteMetric::parse("cpu.usage 42.5 1234567890").unwrap();
+    let with_key = |secret: &[u8], version| Options {
+        strategy: Strategy::keyed(secret, version),
+        ..Options::default()
+    };
+
+    let a = obfuscate(&metric, &with_key(b"secret", 1)).unwrap();
+    let b = obfuscate(&metric, &with_key(b"another", 1)).unwrap();
+    let c = obfuscate(&metric, &with_key(b"secret", 2)).unwrap();
+
+    assert_ne!(a, b);
+    assert!(c.starts_with("obf2_"));
+    assert_eq!(a[5..], c[5..]);
+}
+
+#[test]
+fn test_obf_segments() {
+    let options = Options {
+        segments: true,
+        ..Options::default()
+    };
+    let a = GraphiteMetric::parse("servers.web01.cpu.user;dc=eu 1.0 1234567890").unwrap();
+    let b = GraphiteMetric::parse("servers.web02.cpu.user 1.0 1234567890").unwrap();
+
+    let a = obfuscate(&a, &options).unwrap();
+    let b = obfuscate(&b, &options).unwrap();
+
+    let (name, tags) = a.split_once(';').unwrap();
+    let segments: Vec<&str> = name.split('.').collect();
+    assert_eq!(segments.len(), 4);
+    assert!(segments.iter().all(|s| s.starts_with("obf_")));
+    assert!(tags.starts_with("dc=obf_"));
+
+    // same segments have the same tokens
+    let other: Vec<&str> = b.split('.').collect();
+    assert_eq!(segments[0], other[0]);
+    assert_ne!(segments[1], other[1]);
+    assert_eq!(segments[2..], other[2..]);
+}
+
+#[test]
+fn test_obf_cleartext() {
+    let options = Options {
+        cleartext: Cleartext {
+            tag_keys: vec!["env".to_string()],
+            tag_values: Some(Regex::new("^(ms|bytes)$").unwrap()),
+            segments: vec![0, 3],
+        },
+        ..Options::default()
+    };
+    let metric =
+        GraphiteMetric::parse("prod.web01.cpu.user.total;env=dev;unit=ms;host=web01 1 1").unwrap();
+    let hidden = |value: &str| {
+        let mut buf = String::new();
+        options.strategy.write_token(value, &mut buf);
+        buf
+    };
+
+    let name = obfuscate(&metric, &options).unwrap();
+
+    assert_eq!(
+        name,
+        format!(
+            "prod.{}.user.{};env=dev;host={};unit=ms",
+            hidden("web01.cpu"),
+            hidden("total"),
+            hidden("web01")
+        )
+    );
+}
+
+#[test]
+fn test_obf_cleartext_segments() {
+    let options = Options {
+        segments: true,
+        cleartext: Cleartext {
+            segments: vec![0],
+            ..Cleartext::default()
+        },
+        ..Options::default()
+    };
+    let metric = GraphiteMetric::parse("prod.web01.cpu 1 1").unwrap();
+
+    let name = obfuscate(&metric, &options).unwrap();
+    let segments: Vec<&str> = name.split('.').collect();
+
+    assert_eq!(segments.len(), 3);
+    assert_eq!(segments[0], "prod");
+    assert!(segments[1..].iter().all(|s| s.starts_with("obf_")));
+}
+
+#[test]
+fn test_obf_encrypted_roundtrip() {
+    let options = Options {
+        strategy: Strategy::encrypted(b"secret", 1),
+        cleartext: Cleartext {
+            tag_keys: vec!["env".to_string()],
+            segments: vec![0],
+            ..Cleartext::default()
+        },
+        ..Options::default()
+    };
+    let metric = GraphiteMetric::parse("prod.web01.cpu;host=web01;env=dev 1 1").unwrap();
+
+    let a = obfuscate(&metric, &options).unwrap();
+    let b = obfuscate(&metric, &options).unwrap();
+
+    assert_eq!(a, b);
+    assert!(a.starts_with("prod.enc1_"));
+    assert!(!a.contains("web01"));
+    assert!(
+        a.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-;=".contains(c))
+    );
+    assert_eq!(
+        decrypt(&a, &options.strategy).unwrap(),
+        "prod.web01.cpu;env=dev;host=web01"
+    );
+}
+
+#[test]
+fn test_obf_decrypt_wrong_key() {
+    let options = Options {
+        strategy: Strategy::encrypted(b"secret", 1),
+        ..Options::default()
+    };
+    let metric = GraphiteMetric::parse("cpu.usage 1 1").unwrap();
+    let path = obfuscate(&metric, &options).unwrap();
+
+    let result = decrypt(&path, &Strategy::encrypted(b"another", 1));
+
+    assert!(matches!(result, Err(ObfError::InvalidToken { .. })));
+}
+
+#[test]
+fn test_obf_decrypt_cleartext_prefix() {
+    let strategy = Strategy::encrypted(b"secret", 1);
+    let token = strategy.token("web01");
+
+    // cleartext parts which only start with the prefix are kept
+    let path = format!("enc1_notes.{};env=enc1_x", token);
+    assert_eq!(
+        decrypt(&path, &strategy).unwrap(),
+        "enc1_notes.web01;env=enc1_x"
+    );
+}
+
+#[test]
+fn test_obf_encrypted_concurrent() {
+    let strategy = Strategy::encrypted(b"secret", 1);
+    let expected = strategy.token("cpu.usage");
+
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                for _ in 0..1000 {
+                    let token = strategy.token("cpu.usage");
+                    assert_eq!(token, expected);
+                    assert_eq!(strategy.decrypt(&token).unwrap(), "cpu.usage");
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn test_obf_with_tokens() {
+    let options = Options {
+        cleartext: Cleartext {
+            tag_keys: vec!["env".to_string()],
+            ..Cleartext::default()
+        },
+        ..Options::default()
+    };
+    let metric = GraphiteMetric::parse("cpu.usage;host=web01;env=dev 1 1").unwrap();
+    let mut tokens = Vec::new();
+
+    let path = obfuscate_with(&metric, &options, |clear, token| {
+        tokens.push((clear.to_string(), token.to_string()))
+    })
+    .unwrap();
+
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(tokens[0].0, "cpu.usage");
+    assert_eq!(tokens[1].0, "web01");
+    assert!(path.starts_with(&tokens[0].1));
+    assert!(path.ends_with(&format!(";host={}", tokens[1].1)));
+}
+
+#[test]
+fn test_obf_hash_tag_keys() {
+    let options = Options {
+        hash_tag_keys: vec!["customer_id".to_string(), "env".to_string()],
+        cleartext: Cleartext {
+            tag_keys: vec!["env".to_string()],
+            ..Cleartext::default()
+        },
+        ..Options::default()
+    };
+    let a = GraphiteMetric::parse("app;customer_id=42;env=dev;zone=a 1 1").unwrap();
+    let b = GraphiteMetric::parse("app;zone=a;env=dev;customer_id=42 1 1").unwrap();
+
+    let path = obfuscate(&a, &options).unwrap();
+
+    assert!(!path.contains("customer_id"));
+    assert!(path.contains(";env=dev"));
+    assert!(path.contains(";zone=obf_"));
+    assert_eq!(path, obfuscate(&b, &options).unwrap());
+
+    // stored tags are sorted by obfuscated keys
+    let keys: Vec<&str> = path
+        .split(';')
+        .skip(1)
+        .map(|tag| tag.split_once('=').unwrap().0)
+        .collect();
+    assert!(keys.is_sorted());
+}
+
+#[test]
+fn test_obf_hash_all_tag_keys_decrypt() {
+    let options = Options {
+        strategy: Strategy::encrypted(b"secret", 1),
+        hash_tag_keys: vec!["*".to_string()],
+        ..Options::default()
+    };
+    let metric = GraphiteMetric::parse("app;patient_ward=b2 1 1").unwrap();
+
+    let path = obfuscate(&metric, &options).unwrap();
+
+    assert!(!path.contains("patient_ward"));
+    assert_eq!(
+        decrypt(&path, &options.strategy).unwrap(),
+        "app;patient_ward=b2"
+    );
+}
+
+#[test]
+fn test_obf_rotation_window() {
+    let options = Options {
+        strategy: Strategy::keyed(b"new secret", 2),
+        rotation: Some(Rotation {
+            strategy: Strategy::keyed(b"secret", 1),
+            strategies: Strategies::default(),
+            until: 1700000000,
+        }),
+        ..Options::default()
+    };
+    let metric = GraphiteMetric::parse("cpu.usage;host=server01 42.5 1234567890").unwrap();
+
+    assert!(options.rotating(1699999999));
+    assert!(!options.rotating(1700000000));
+    assert!(!Options::default().rotating(0));
+
+    let previous = options.previous().unwrap();
+    assert_eq!(previous.strategy.version(), 1);
+    assert!(previous.rotation.is_none());
+    assert_eq!(
+        obfuscate(&metric, &previous).unwrap(),
+        "obf1_bd13cac033a57ba5;host=obf1_fa556ee314fd809b"
+    );
+    assert!(obfuscate(&metric, &options).unwrap().starts_with("obf2_"));
+    assert!(Options::default().previous().is_none());
+}
+
+#[test]
+fn test_obf_format() {
+    let metric = GraphiteMetric::parse("cpu.usage 42.5 1234567890").unwrap();
+    let with_format = |prefix: Option<&str>, len, encoding| Options {
+        strategy: Strategy::keyed(b"secret", 1).with_format(&Format {
+            prefix: prefix.map(str::to_string),
+            len,
+            encoding,
+        }),
+        ..Options::default()
+    };
+
+    let base32 = with_format(None, 8, Encoding::Base32);
+    assert_eq!(obfuscate(&metric, &base32).unwrap(), "obf1_xuj4vqbtuv52k");
+
+    let long = with_format(Some("m{version}-"), 16, Encoding::Hex);
+    let name = obfuscate(&metric, &long).unwrap();
+    assert_eq!(name.len(), 3 + 32);
+    assert!(name.starts_with("m1-bd13cac033a57ba5"));
+
+    assert_eq!("base32".parse::<Encoding>(), Ok(Encoding::Base32));
+    assert!("base64".parse::<Encoding>().is_err());
+}
+
+#[test]
+fn test_obf_format_check() {
+    let prefix = |prefix: &str| Format {
+        prefix: Some(prefix.to_string()),
+        ..Format::default()
+    };
+
+    assert!(Format::default().check().is_ok());
+    assert!(prefix("m{version}-").check().is_ok());
+    for invalid in ["", "m.", "m;", "m=", "m ", "m\t"] {
+        assert!(prefix(invalid).check().is_err(), "{:?}", invalid);
+    }
+    for len in [3, 33] {
+        let format = Format {
+            len,
+            ..Format::default()
+        };
+        assert!(format.check().is_err());
+    }
+}
+
+#[test]
+fn test_obf_routes() {
+    let options = Options {
+        strategy: Strategy::keyed(b"secret", 1),
+        ..Options::default()
+    };
+    let routes = Routes::parse(
+        "# public namespace\nglob:public.* passthrough\nglob:servers.*.* segments\n",
+        &options,
+    )
+    .unwrap();
+    assert_eq!(routes.routes().len(), 2);
+
+    let obfuscate = |line: &str| {
+        let metric = GraphiteMetric::parse(line).unwrap();
+        routes.obfuscate(&metric).unwrap()
+    };
+
+    assert_eq!(obfuscate("public.uptime;dc=eu 1 1"), "public.uptime;dc=eu");
+    assert_eq!(
+        obfuscate("servers.web01.cpu 1 1").matches("obf1_").count(),
+        3
+    );
+    assert_eq!(
+        obfuscate("cpu.usage;host=server01 1 1"),
+        "obf1_bd13cac033a57ba5;host=obf1_fa556ee314fd809b"
+    );
+
+    assert!(Routes::parse("glob:* hide", &options).is_err());
+    assert!(Routes::parse("glob:*", &options).is_err());
+}
+
+#[test]
+fn test_obf_routes_strategies() {
+    // encrypt mode with keyed hashing of servers
+    let encrypted = Strategy::encrypted(b"secret", 1);
+    let options = Options {
+        strategies: Strategies::new(&encrypted, Some(b"secret"), 1, &Format::default()),
+        strategy: encrypted.clone(),
+        ..Options::default()
+    };
+    let routes =
+        Routes::parse("glob:servers.* keyed\nglob:billing.* encrypted\n", &options).unwrap();
+
+    let obfuscate = |line: &str| {
+        let metric = GraphiteMetric::parse(line).unwrap();
+        routes.obfuscate(&metric).unwrap()
+    };
+
+    let keyed = Strategy::keyed(b"secret", 1);
+    assert_eq!(obfuscate("servers.cpu 1 1"), keyed.token("servers.cpu"));
+
+    let billing = obfuscate("billing.revenue;dc=eu 1 1");
+    assert!(billing.starts_with("enc1_"));
+    assert_eq!(
+        decrypt(&billing, &encrypted).unwrap(),
+        "billing.revenue;dc=eu"
+    );
+
+    // strategies need a key
+    let legacy = Options::default();
+    assert!(Routes::parse("glob:* keyed", &legacy).is_err());
+    assert!(Routes::parse("glob:* encrypted", &legacy).is_err());
+}
+
+#[test]
+fn test_obf_custom_obfuscator() {
+    struct Redact;
+    impl Obfuscator for Redact {
+        fn obfuscate_with(
+            &self,
+            metric: &GraphiteMetric,
+            on_token: &mut dyn FnMut(&str, &str),
+        ) -> Result<String, ObfError> {
+            on_token(metric.name, "redacted");
+            Ok("redacted".to_string())
+        }
+    }
+
+    let mut routes = Routes::new(Box::new(Options::default()));
+    routes.route("^secret\\.", Box::new(Redact)).unwrap();
+
+    let metric = GraphiteMetric::parse("secret.plans 1 1").unwrap();
+    let mut tokens = Vec::new();
+    let path = routes
+        .obfuscate_with(&metric, &mut |clear, token| {
+            tokens.push((clear.to_string(), token.to_string()))
+        })
+        .unwrap();
+
+    assert_eq!(path, "redacted");
+    assert_eq!(
+        tokens,
+        vec![("secret.plans".to_string(), "redacted".to_string())]
+    );
+}
+
+#[test]
+fn test_obf_is_token() {
+    let keyed = Strategy::keyed(b"secret", 1);
+    assert!(keyed.is_token("obf1_bd13cac033a57ba5"));
+    assert!(!keyed.is_token("obf1_bd13cac033a57ba"));
+    assert!(!keyed.is_token("obf1_server01"));
+    assert!(!keyed.is_token("obf_bd13cac033a57ba5"));
+
+    let base32 = keyed.with_format(&Format {
+        encoding: Encoding::Base32,
+        ..Format::default()
+    });
+    assert!(base32.is_token("obf1_xuj4vqbtuv52k"));
+    assert!(!base32.is_token("obf1_bd13cac033a57ba5"));
+
+    let encrypted = Strategy::encrypted(b"secret", 1);
+    assert!(encrypted.is_token(&encrypted.token("server01")));
+    assert!(!encrypted.is_token("enc1_short"));
+}
+
+#[test]
+fn test_obf_guard() {
+    let options = Options {
+        strategy: Strategy::keyed(b"secret", 1),
+        cleartext: Cleartext {
+            tag_keys: vec!["env".to_string()],
+            segments: vec![0],
+            ..Cleartext::default()
+        },
+        ..Options::default()
+    };
+    let guard = Guard::new(
+        vec![options.strategy.clone()],
+        Some("prod|staging"),
+        Some("env|host"),
+        Some("prod|staging"),
+    )
+    .unwrap();
+
+    let check = |line: &str| {
+        let metric = GraphiteMetric::parse(line).unwrap();
+        guard.check(&obfuscate(&metric, &options).unwrap())
+    };
+
+    assert!(check("prod.cpu.usage;env=prod;host=web01 1 1").is_ok());
+    assert_eq!(
+        check("customer42.cpu.usage;host=web01 1 1"),
+        Err(Leak::Segment("customer42".to_string()))
+    );
+    assert_eq!(
+        check("prod.cpu;env=customer42 1 1"),
+        Err(Leak::TagValue("customer42".to_string()))
+    );
+    assert_eq!(
+        check("prod.cpu;patient_ward=a 1 1"),
+        Err(Leak::TagKey("patient_ward".to_string()))
+    );
+
+    // anchored patterns, the whole part has to match
+    assert!(guard.check("production.obf1_bd13cac033a57ba5").is_err());
+    assert!(Guard::new(Vec::new(), Some("("), None, None).is_err());
+}
+
+#[test]
+fn test_obf_rare_kept() {
+    let metric = GraphiteMetric::parse("app.requests;customer=obf_rare 1 1").unwrap();
+
+    let name = obfuscate(&metric, &Options::default()).unwrap();
+
+    assert!(name.ends_with(";customer=obf_rare"));
+}
+
+// options of golden vectors, see golden.txt
+fn golden_options(spec: &str) -> Options {
+    let mut options = Options::default();
+    let (mut secret, mut version, mut mode) = ("", 1, "hash");
+    let mut format = Format::default();
+
+    for option in spec.split(',') {
+        match option.split_once('=').unwrap_or((option, "")) {
+            ("key", value) => secret = value,
+            ("version", value) => version = value.parse().unwrap(),
+            ("mode", value) => mode = value,
+            ("prefix", value) => format.prefix = Some(value.to_string()),
+            ("len", value) => format.len = value.parse().unwrap(),
+            ("encoding", value) => format.encoding = value.parse().unwrap(),
+            ("segments", _) => options.segments = true,
+            ("clear_segments", value) => options.cleartext.segments.push(value.parse().unwrap()),
+            ("clear_keys", value) => options.cleartext.tag_keys.push(value.to_string()),
+            ("hash_keys", value) => options.hash_tag_keys.push(value.to_string()),
+            ("keep_order", _) => options.canonical_tags = false,
+            (other, _) => panic!("unknown golden option: {}", other),
+        }
+    }
+
+    options.strategy = match mode {
+        "hash" => Strategy::keyed(secret.as_bytes(), version),
+        "encrypt" => Strategy::encrypted(secret.as_bytes(), version),
+        other => panic!("unknown golden mode: {}", other),
+    }
+    .with_format(&format);
+    options
+}
+
+#[test]
+fn test_obf_golden_vectors() {
+    let golden = include_str!("golden.txt");
+
+    for line in golden
+        .lines()
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+    {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [spec, path, expected] = fields[..] else {
+            panic!("invalid golden vector: {}", line);
+        };
+
+        let line = format!("{} 1 1", path);
+        let metric = GraphiteMetric::parse(&line).unwrap();
+        let actual = obfuscate(&metric, &golden_options(spec)).unwrap();
+        assert_eq!(actual, expected, "{}", line);
+    }
+}
@@ -0,0 +1,306 @@
+// Detector of personal data in metric names, tag keys and tag values,
+// matched content is hashed or redacted before obfuscation, so it never
+// reaches the storage even if cleartext rules or routes would let it
+// through. Name segments overlapped by a match are replaced as a whole,
+// so the segment count and positions of cleartext segments are kept.
+// Matches are hashed with the key of the written path, so series keep
+// their identity with both keys during rotation.
+//
+// Built-in patterns are enabled by name, custom patterns are read from
+// a file, one `<name> <regex>` per line:
+//
+//     customer ^cust[0-9]+$
+//     token    [A-Za-z0-9]{32,}
+use crate::libs::graphite::GraphiteMetric;
+use crate::libs::obf::Strategy;
+use regex::Regex;
+use std::borrow::Cow;
+
+pub const REDACTED: &str = "redacted";
+
+// name and pattern of built-in detectors
+const BUILTIN: &[(&str, &str)] = &[
+    // dotted or sanitized (`10_0_0_1`) addresses
+    ("ipv4", r"\b\d{1,3}[._-]\d{1,3}[._-]\d{1,3}[._-]\d{1,3}\b"),
+    ("ipv6", r"(?i)\b(?:[0-9a-f]{0,4}:){2,7}[0-9a-f]{1,4}\b"),
+    (
+        "email",
+        r"[A-Za-z0-9_%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+    ),
+    (
+        "uuid",
+        r"(?i)\b[0-9a-f]{8}[-_][0-9a-f]{4}[-_][0-9a-f]{4}[-_][0-9a-f]{4}[-_][0-9a-f]{12}\b",
+    ),
+    ("digits", r"\d{8,}"),
+];
+
+// what to do with matched content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    // replace with a token of the obfuscation strategy, keeps identity
+    Hash,
+    // replace with `redacted`
+    Redact,
+}
+
+impl std::str::FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hash" => Ok(Action::Hash),
+            "redact" => Ok(Action::Redact),
+            other => Err(format!("unknown pii action: {}", other)),
+        }
+    }
+}
+
+pub struct Pattern {
+    pub name: String,
+    regex: Regex,
+}
+
+pub struct Detector {
+    patterns: Vec<Pattern>,
+    action: Action,
+    strategy: Strategy,
+    // previous key during rotation, see `Redacted::apply_previous`
+    previous: Option<Strategy>,
+}
+
+// replacements of detected content, metric borrows them after `apply`
+#[derive(Default)]
+pub struct Redacted {
+    name: Option<String>,
+    keys: Vec<(usize, String)>,
+    values: Vec<(usize, String)>,
+    // the same replacements hashed with the previous key
+    previous: Option<Box<Redacted>>,
+}
+
+impl Redacted {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.keys.is_empty() && self.values.is_empty()
+    }
+
+    pub fn apply<'a>(&'a self, metric: &mut GraphiteMetric<'a>) {
+        if let Some(name) = &self.name {
+            metric.name = name;
+        }
+        for (i, key) in &self.keys {
+            metric.tags[*i].0 = key;
+        }
+        for (i, value) in &self.values {
+            metric.tags[*i].1 = value;
+        }
+    }
+
+    // hashed with the previous key too, see `apply_previous`
+    pub fn has_previous(&self) -> bool {
+        self.previous.is_some()
+    }
+
+    // swap replacements of the current key applied to metric for the
+    // previous key ones, so previous key paths stay continuous during
+    // rotation, fields changed since `apply` (rare values) are kept
+    pub fn apply_previous<'a>(&'a self, metric: &mut GraphiteMetric<'a>) {
+        let Some(previous) = &self.previous else {
+            return;
+        };
+        let current = |fields: &[(usize, String)], i: usize, field: &str| {
+            fields.iter().any(|(j, f)| *j == i && f == field)
+        };
+
+        if let (Some(current), Some(name)) = (&self.name, &previous.name)
+            && metric.name == current
+        {
+            metric.name = name;
+        }
+        for (i, key) in &previous.keys {
+            if current(&self.keys, *i, metric.tags[*i].0) {
+                metric.tags[*i].0 = key;
+            }
+        }
+        for (i, value) in &previous.values {
+            if current(&self.values, *i, metric.tags[*i].1) {
+                metric.tags[*i].1 = value;
+            }
+        }
+    }
+}
+
+impl Detector {
+    pub fn new(action: Action, strategy: Strategy) -> Self {
+        Self {
+            patterns: Vec::new(),
+            action,
+            strategy,
+            previous: None,
+        }
+    }
+
+    // hash matches with the previous key too, for previous key paths
+    // during rotation
+    pub fn previous(&mut self, strategy: Strategy) {
+        self.previous = Some(strategy);
+    }
+
+    // enable built-in pattern: ipv4, ipv6, email, uuid or digits
+    pub fn builtin(&mut self, name: &str) -> Result<(), String> {
+        let (_, pattern) = BUILTIN
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .ok_or_else(|| format!("unknown built-in pii pattern: {}", name))?;
+        self.pattern(name, pattern)
+    }
+
+    pub fn pattern(&mut self, name: &str, pattern: &str) -> Result<(), String> {
+        let regex =
+            Regex::new(pattern).map_err(|e| format!("invalid pii pattern {}: {}", name, e))?;
+        self.patterns.push(Pattern {
+            name: name.to_string(),
+            regex,
+        });
+        Ok(())
+    }
+
+    // add custom patterns, empty lines and `#` comments are skipped
+    pub fn parse(&mut self, content: &str) -> Result<(), String> {
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, pattern) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| format!("line {}: expected `name regex`", n + 1))?;
+            self.pattern(name, pattern.trim())
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read pii patterns {}: {}", path, e))?;
+        self.parse(&content)
+    }
+
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    fn replacement(&self, text: &str, strategy: &Strategy) -> String {
+        match self.action {
+            Action::Hash => strategy.token(text),
+            Action::Redact => REDACTED.to_string(),
+        }
+    }
+
+    // replace every match of every pattern, `detected` is called once
+    // per pattern matched in the field
+    fn redact<'t, F>(&self, text: &'t str, strategy: &Strategy, detected: &mut F) -> Cow<'t, str>
+    where
+        F: FnMut(&Pattern),
+    {
+        let mut text = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            if !pattern.regex.is_match(&text) {
+                continue;
+            }
+            detected(pattern);
+
+            let replaced = pattern.regex.replace_all(&text, |caps: &regex::Captures| {
+                self.replacement(&caps[0], strategy)
+            });
+            text = Cow::Owned(replaced.into_owned());
+        }
+        text
+    }
+
+    // replace every name segment overlapped by a match of any pattern,
+    // dotted matches (emails, addresses) never change the segment count
+    fn redact_name<'t, F>(
+        &self,
+        name: &'t str,
+        strategy: &Strategy,
+        detected: &mut F,
+    ) -> Cow<'t, str>
+    where
+        F: FnMut(&Pattern),
+    {
+        let mut hidden = vec![false; name.split('.').count()];
+        for pattern in &self.patterns {
+            let mut matched = false;
+            for m in pattern.regex.find_iter(name).filter(|m| !m.is_empty()) {
+                let first = name[..m.start()].matches('.').count();
+                let last = name[..m.end()].matches('.').count();
+                // already replaced by previous patterns
+                if hidden[first..=last].iter().all(|&h| h) {
+                    continue;
+                }
+                hidden[first..=last].fill(true);
+                matched = true;
+            }
+            if matched {
+                detected(pattern);
+            }
+        }
+        if !hidden.contains(&true) {
+            return Cow::Borrowed(name);
+        }
+
+        let segments: Vec<Cow<str>> = name
+            .split('.')
+            .zip(hidden)
+            .map(|(segment, hidden)| match hidden {
+                true => Cow::Owned(self.replacement(segment, strategy)),
+                false => Cow::Borrowed(segment),
+            })
+            .collect();
+        Cow::Owned(segments.join("."))
+    }
+
+    fn render<F>(&self, metric: &GraphiteMetric, strategy: &Strategy, detected: &mut F) -> Redacted
+    where
+        F: FnMut(&Pattern),
+    {
+        let mut redacted = Redacted::default();
+        if let Cow::Owned(name) = self.redact_name(metric.name, strategy, detected) {
+            redacted.name = Some(name);
+        }
+        for (i, (key, value)) in metric.tags.iter().enumerate() {
+            if let Cow::Owned(key) = self.redact(key, strategy, detected) {
+                redacted.keys.push((i, key));
+            }
+            if let Cow::Owned(value) = self.redact(value, strategy, detected) {
+                redacted.values.push((i, value));
+            }
+        }
+        redacted
+    }
+
+    // scan metric name, tag keys and tag values
+    pub fn scan<F>(&self, metric: &GraphiteMetric, mut detected: F) -> Redacted
+    where
+        F: FnMut(&Pattern),
+    {
+        if self.patterns.is_empty() {
+            return Redacted::default();
+        }
+
+        let mut redacted = self.render(metric, &self.strategy, &mut detected);
+        if let Some(previous) = &self.previous
+            && self.action == Action::Hash
+            && !redacted.is_empty()
+        {
+            let previous = self.render(metric, previous, &mut |_| {});
+            redacted.previous = Some(Box::new(previous));
+        }
+        redacted
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,175 @@
+use super::*;
+
+fn detector(action: Action) -> Detector {
+    let mut detector = Detector::new(action, Strategy::keyed(b"secret", 1));
+    for name in ["ipv4", "ipv6", "email", "uuid", "digits"] {
+        detector.builtin(name).unwrap();
+    }
+    detector
+}
+
+fn scan(detector: &Detector, line: &str) -> (String, Vec<String>) {
+    let mut detected = Vec::new();
+    let redacted = detector.scan(&GraphiteMetric::parse(line).unwrap(), |pattern| {
+        detected.push(pattern.name.clone())
+    });
+
+    // replacements have to outlive the metric
+    let mut metric = GraphiteMetric::parse(line).unwrap();
+    redacted.apply(&mut metric);
+
+    let tags: String = metric
+        .tags
+        .iter()
+        .map(|(key, value)| format!(";{}={}", key, value))
+        .collect();
+    (format!("{}{}", metric.name, tags), detected)
+}
+
+#[test]
+fn test_pii_builtin() {
+    let detector = detector(Action::Redact);
+
+    let cases = [
+        ("servers.10_0_0_1.cpu", "servers.redacted.cpu", "ipv4"),
+        ("app.users;ip=192.168.1.10", "app.users;ip=redacted", "ipv4"),
+        (
+            "app.users;ip=fe80::1ff:fe23:4567:890a",
+            "app.users;ip=redacted",
+            "ipv6",
+        ),
+        (
+            "app.logins;user=john@example.com",
+            "app.logins;user=redacted",
+            "email",
+        ),
+        (
+            "jobs.123e4567-e89b-12d3-a456-426614174000.runs",
+            "jobs.redacted.runs",
+            "uuid",
+        ),
+        (
+            "app.users.u1234567890.logins",
+            "app.users.redacted.logins",
+            "digits",
+        ),
+        (
+            "app.logins.john@example.com",
+            "app.logins.redacted.redacted",
+            "email",
+        ),
+        (
+            "app.logins;john@example.com=1",
+            "app.logins;redacted=1",
+            "email",
+        ),
+    ];
+    for (path, expected, pattern) in cases {
+        let (redacted, detected) = scan(&detector, &format!("{} 1 1", path));
+        assert_eq!(redacted, expected, "{}", path);
+        assert_eq!(detected, vec![pattern], "{}", path);
+    }
+
+    let (clean, detected) = scan(&detector, "servers.web01.cpu;dc=eu-west-1 1 1");
+    assert_eq!(clean, "servers.web01.cpu;dc=eu-west-1");
+    assert!(detected.is_empty());
+}
+
+#[test]
+fn test_pii_hash() {
+    let detector = detector(Action::Hash);
+
+    let (a, _) = scan(&detector, "app.logins;user=john@example.com 1 1");
+    let (b, _) = scan(&detector, "app.sessions;user=john@example.com 1 1");
+
+    assert!(a.starts_with("app.logins;user=obf1_"));
+    assert!(!a.contains("john"));
+    assert_eq!(a.split_once('=').unwrap().1, b.split_once('=').unwrap().1);
+}
+
+#[test]
+fn test_pii_custom_patterns() {
+    let mut detector = Detector::new(Action::Redact, Strategy::default());
+    detector
+        .parse("# customers\ncustomer ^cust[0-9]+$\n\n")
+        .unwrap();
+    assert_eq!(detector.patterns().len(), 1);
+
+    let (redacted, detected) = scan(&detector, "billing.cust42.orders;owner=cust7 1 1");
+    assert_eq!(redacted, "billing.cust42.orders;owner=redacted");
+    assert_eq!(detected, vec!["customer"]);
+
+    assert!(detector.parse("broken").is_err());
+    assert!(detector.parse("broken (").is_err());
+    assert!(detector.builtin("phone").is_err());
+}
+
+#[test]
+fn test_pii_clear_segments_kept() {
+    use crate::libs::obf::{self, Cleartext, Options};
+
+    let detector = detector(Action::Redact);
+    let options = Options {
+        strategy: Strategy::keyed(b"secret", 1),
+        cleartext: Cleartext {
+            segments: vec![0, 2],
+            ..Cleartext::default()
+        },
+        ..Options::default()
+    };
+
+    // `com` is the cleartext segment, the customer never takes its place
+    let (redacted, _) = scan(&detector, "app.john@example.com.42customer 1 1");
+    assert_eq!(redacted, "app.redacted.redacted.42customer");
+
+    let line = format!("{} 1 1", redacted);
+    let metric = GraphiteMetric::parse(&line).unwrap();
+    let path = obf::obfuscate(&metric, &options).unwrap();
+    assert!(path.starts_with("app.obf1_"));
+    assert!(path.contains(".redacted.obf1_"));
+    assert!(!path.contains("42customer"));
+}
+
+#[test]
+fn test_pii_previous_key() {
+    use crate::libs::obf::{self, Options, RARE};
+
+    let previous = Strategy::keyed(b"secret", 1);
+    let line = "app.john@example.com;ip=10.0.0.1;user=jane@example.com 1 1";
+    let options = Options {
+        strategy: previous.clone(),
+        ..Options::default()
+    };
+
+    // path written with the previous key before rotation
+    let mut before = Detector::new(Action::Hash, previous.clone());
+    before.builtin("email").unwrap();
+    before.builtin("ipv4").unwrap();
+    let redacted = before.scan(&GraphiteMetric::parse(line).unwrap(), |_| {});
+    assert!(!redacted.has_previous());
+    let mut metric = GraphiteMetric::parse(line).unwrap();
+    redacted.apply(&mut metric);
+    let expected = obf::obfuscate(&metric, &options).unwrap();
+
+    // the same path written with the previous key during rotation
+    let mut during = Detector::new(Action::Hash, Strategy::keyed(b"new secret", 2));
+    during.previous(previous);
+    during.builtin("email").unwrap();
+    during.builtin("ipv4").unwrap();
+    let redacted = during.scan(&GraphiteMetric::parse(line).unwrap(), |_| {});
+    assert!(redacted.has_previous());
+    let mut metric = GraphiteMetric::parse(line).unwrap();
+    redacted.apply(&mut metric);
+    assert!(metric.name.starts_with("app.obf2_"));
+
+    let mut source = metric.clone();
+    redacted.apply_previous(&mut source);
+    assert_eq!(obf::obfuscate(&source, &options).unwrap(), expected);
+
+    // rare values bucketed after `apply` are kept
+    metric.tags[1].1 = RARE;
+    let mut source = metric.clone();
+    redacted.apply_previous(&mut source);
+    assert_eq!(source.tags[1].1, RARE);
+    assert!(source.tags[0].1.starts_with("obf1_"));
+}
@@ -15,6 +15,28 @@ pub struct Labels {
     pub project: String,
 }
 
+// labels for per-rule counters
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct RuleLabels {
+    pub rule: String,
+    pub worker_id: String,
+    pub application: String,
+    pub circuit: String,
+    pub env: String,
+    pub project: String,
+}
+
+// labels for per-key-version counters
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct KeyLabels {
+    pub key_version: String,
+    pub worker_id: String,
+    pub application: String,
+    pub circuit: String,
+    pub env: String,
+    pub project: String,
+}
+
 #[derive(Clone)]
 pub struct Prometheus {
     registry: Arc<Mutex<Registry>>,
@@ -25,6 +47,20 @@ pub struct Prometheus {
     pub processed: Family<Labels, Counter>,
     pub errors: Family<Labels, Counter>,
     pub dropped: Family<Labels, Counter>,
+    pub rejected: Family<Labels, Counter>,
+    pub mapped: Family<Labels, Counter>,
+    pub mapping_dropped: Family<Labels, Counter>,
+    pub collisions: Family<Labels, Counter>,
+    pub leaked: Family<Labels, Counter>,
+    pub cache_hits: Family<Labels, Counter>,
+    pub cache_misses: Family<Labels, Counter>,
+    pub cache_evictions: Family<Labels, Counter>,
+    pub rewritten: Family<RuleLabels, Counter>,
+    pub filtered: Family<RuleLabels, Counter>,
+    pub transformed: Family<RuleLabels, Counter>,
+    pub pii_detected: Family<RuleLabels, Counter>,
+    pub rare: Family<RuleLabels, Counter>,
+    pub obfuscated: Family<KeyLabels, Counter>,
 }
 
 impl Labels {
@@ -47,6 +83,28 @@ impl Labels {
             project: self.project.clone(),
         }
     }
+
+    pub fn rule(&self, rule: &str) -> RuleLabels {
+        RuleLabels {
+            rule: rule.to_string(),
+            worker_id: self.worker_id.clone(),
+            application: self.application.clone(),
+            circuit: self.circuit.clone(),
+            env: self.env.clone(),
+            project: self.project.clone(),
+        }
+    }
+
+    pub fn key(&self, version: u32) -> KeyLabels {
+        KeyLabels {
+            key_version: version.to_string(),
+            worker_id: self.worker_id.clone(),
+            application: self.application.clone(),
+            circuit: self.circuit.clone(),
+            env: self.env.clone(),
+            project: self.project.clone(),
+        }
+    }
 }
 
 impl Prometheus {
@@ -59,6 +117,20 @@ impl Prometheus {
         let processed = Family::<Labels, Counter>::default();
         let errors = Family::<Labels, Counter>::default();
         let dropped = Family::<Labels, Counter>::default();
+        let rejected = Family::<Labels, Counter>::default();
+        let mapped = Family::<Labels, Counter>::default();
+        let mapping_dropped = Family::<Labels, Counter>::default();
+        let collisions = Family::<Labels, Counter>::default();
+        let leaked = Family::<Labels, Counter>::default();
+        let cache_hits = Family::<Labels, Counter>::default();
+        let cache_misses = Family::<Labels, Counter>::default();
+        let cache_evictions = Family::<Labels, Counter>::default();
+        let rewritten = Family::<RuleLabels, Counter>::default();
+        let filtered = Family::<RuleLabels, Counter>::default();
+        let transformed = Family::<RuleLabels, Counter>::default();
+        let pii_detected = Family::<RuleLabels, Counter>::default();
+        let rare = Family::<RuleLabels, Counter>::default();
+        let obfuscated = Family::<KeyLabels, Counter>::default();
 
         registry.register("received", "Number of messages received", received.clone());
 
@@ -72,6 +144,90 @@ impl Prometheus {
 
         registry.register("dropped", "Number of messages dropped", dropped.clone());
 
+        registry.register(
+            "rejected",
+            "Number of metrics rejected by obfuscation limits",
+            rejected.clone(),
+        );
+
+        registry.register(
+            "mapped",
+            "Number of new obfuscation mappings queued",
+            mapped.clone(),
+        );
+
+        registry.register(
+            "mapping_dropped",
+            "Number of obfuscation mappings dropped on full mapping channel",
+            mapping_dropped.clone(),
+        );
+
+        registry.register(
+            "collisions",
+            "Number of obfuscation tokens produced by different inputs",
+            collisions.clone(),
+        );
+
+        registry.register(
+            "leaked",
+            "Number of metrics blocked by output leak guard",
+            leaked.clone(),
+        );
+
+        registry.register(
+            "cache_hits",
+            "Number of obfuscated paths taken from cache",
+            cache_hits.clone(),
+        );
+
+        registry.register(
+            "cache_misses",
+            "Number of obfuscated paths missing in cache",
+            cache_misses.clone(),
+        );
+
+        registry.register(
+            "cache_evictions",
+            "Number of obfuscated paths evicted from cache",
+            cache_evictions.clone(),
+        );
+
+        registry.register(
+            "rewritten",
+            "Number of metric names rewritten by rule",
+            rewritten.clone(),
+        );
+
+        registry.register(
+            "filtered",
+            "Number of metrics dropped by allow and block lists",
+            filtered.clone(),
+        );
+
+        registry.register(
+            "transformed",
+            "Number of values transformed by rule",
+            transformed.clone(),
+        );
+
+        registry.register(
+            "pii_detected",
+            "Number of personal data detections by pattern",
+            pii_detected.clone(),
+        );
+
+        registry.register(
+            "rare",
+            "Number of rare tag values bucketed by tag key",
+            rare.clone(),
+        );
+
+        registry.register(
+            "obfuscated",
+            "Number of points written by obfuscation key version",
+            obfuscated.clone(),
+        );
+
         Self {
             registry: Arc::new(Mutex::new(registry)),
             labels,
@@ -79,6 +235,20 @@ impl Prometheus {
             processed,
             errors,
             dropped,
+            rejected,
+            mapped,
+            mapping_dropped,
+            collisions,
+            leaked,
+            cache_hits,
+            cache_misses,
+            cache_evictions,
+            rewritten,
+            filtered,
+            transformed,
+            pii_detected,
+            rare,
+            obfuscated,
         }
     }
 
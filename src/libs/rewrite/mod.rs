@@ -0,0 +1,146 @@
+// Carbon-style rewrite rules for metric names, see carbon's rewrite-rules.conf:
+//
+//     [pre]
+//     ^servers\.(\w+)\.example\.com\. = servers.\1.
+//     [post]
+//     _sum$ =
+//
+// Rules are applied in file order, `[pre]` section first and `[post]` next,
+// each rule replaces all matches of its pattern in the metric name.
+use regex::Regex;
+use std::borrow::Cow;
+
+pub struct Rule {
+    pub pattern: String,
+    regex: Regex,
+    replacement: String,
+}
+
+#[derive(Default)]
+pub struct Rewriter {
+    rules: Vec<Rule>,
+}
+
+// translate python replacement (`\1`, `\g<name>`) into regex crate syntax
+fn replacement(python: &str) -> String {
+    let mut out = String::with_capacity(python.len());
+    let mut chars = python.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '$' => out.push_str("$$"),
+            '\\' => match chars.peek() {
+                Some(d) if d.is_ascii_digit() => {
+                    out.push_str("${");
+                    while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                        out.push(*d);
+                        chars.next();
+                    }
+                    out.push('}');
+                }
+                Some('g') => {
+                    chars.next();
+                    out.push_str("${");
+                    if chars.peek() == Some(&'<') {
+                        chars.next();
+                    }
+                    for d in chars.by_ref() {
+                        if d == '>' {
+                            break;
+                        }
+                        out.push(d);
+                    }
+                    out.push('}');
+                }
+                Some('\\') => {
+                    chars.next();
+                    out.push('\\');
+                }
+                _ => out.push('\\'),
+            },
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+impl Rule {
+    pub fn new(pattern: &str, python_replacement: &str) -> Result<Self, String> {
+        let regex = Regex::new(pattern)
+            .map_err(|e| format!("invalid rewrite pattern {:?}: {}", pattern, e))?;
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex,
+            replacement: replacement(python_replacement),
+        })
+    }
+}
+
+impl Rewriter {
+    // parse rules from rewrite-rules.conf content
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let (mut pre, mut post) = (Vec::new(), Vec::new());
+        let mut section = "pre".to_string();
+
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_lowercase();
+                continue;
+            }
+
+            let (pattern, repl) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `pattern = replacement`", n + 1))?;
+            let rule = Rule::new(pattern.trim(), repl.trim())
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+
+            match section.as_str() {
+                "pre" => pre.push(rule),
+                "post" => post.push(rule),
+                other => return Err(format!("line {}: unknown section [{}]", n + 1, other)),
+            }
+        }
+
+        pre.append(&mut post);
+        Ok(Self { rules: pre })
+    }
+
+    // load rules from rewrite-rules.conf file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read rewrite rules {}: {}", path, e))?;
+        Self::parse(&content)
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    // rewrite metric name, `matched` is called for every applied rule
+    pub fn apply<'a, F>(&self, name: &'a str, mut matched: F) -> Cow<'a, str>
+    where
+        F: FnMut(&Rule),
+    {
+        let mut name = Cow::Borrowed(name);
+
+        for rule in &self.rules {
+            if let Cow::Owned(rewritten) = rule.regex.replace_all(&name, rule.replacement.as_str())
+            {
+                matched(rule);
+                name = Cow::Owned(rewritten);
+            }
+        }
+
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests;
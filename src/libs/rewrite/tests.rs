@@ -0,0 +1,55 @@
+use super::*;
+
+const RULES: &str = r#"
+# strip hostname suffix
+[pre]
+^servers\.(\w+)\.example\.com\. = servers.\1.
+^collectd\. =
+
+[post]
+_sum$ =
+(?P<app>\w+)-(?P<env>prod|dev)\. = \g<env>.\g<app>.
+"#;
+
+#[test]
+fn test_rewrite_parse() {
+    let rewriter = Rewriter::parse(RULES).unwrap();
+
+    assert_eq!(rewriter.rules().len(), 4);
+    assert_eq!(
+        rewriter.rules()[0].pattern,
+        r"^servers\.(\w+)\.example\.com\."
+    );
+}
+
+#[test]
+fn test_rewrite_apply() {
+    let rewriter = Rewriter::parse(RULES).unwrap();
+    let mut matched = Vec::new();
+
+    let name = rewriter.apply("servers.web01.example.com.cpu_sum", |rule| {
+        matched.push(rule.pattern.clone())
+    });
+    assert_eq!(name, "servers.web01.cpu");
+    assert_eq!(matched.len(), 2);
+
+    let name = rewriter.apply("collectd.billing-prod.load", |_| {});
+    assert_eq!(name, "prod.billing.load");
+
+    let name = rewriter.apply("untouched.metric", |_| panic!("no rule should match"));
+    assert!(matches!(name, Cow::Borrowed("untouched.metric")));
+}
+
+#[test]
+fn test_rewrite_invalid() {
+    assert!(Rewriter::parse("no separator").is_err());
+    assert!(Rewriter::parse("(unclosed = x").is_err());
+    assert!(Rewriter::parse("[aggregate]\na = b").is_err());
+}
+
+#[test]
+fn test_rewrite_literal_dollar() {
+    let rewriter = Rewriter::parse(r"cost = $usd").unwrap();
+
+    assert_eq!(rewriter.apply("app.cost", |_| {}), "app.$usd");
+}
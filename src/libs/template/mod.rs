@@ -0,0 +1,178 @@
+// Graphite templates which turn positional segments of untagged
+// dotted names into tags, one template per line:
+//
+//     [filter] template [key=value,...]
+//
+//     servers.* .dc.host.measurement*
+//     *.app env.service.resource.measurement region=eu
+//     measurement*
+//
+// Filter is matched segment by segment (`*` matches any segment) against
+// the beginning of the name, the first matching template wins and a
+// template without filter matches everything. Template parts are:
+//
+// - `measurement`: segment is a part of the new name, parts are joined with `.`
+// - `measurement*`: this and all remaining segments are the new name
+// - empty part: segment is skipped
+// - any other word: segment becomes the value of a tag with this key
+//
+// Segments beyond the template are dropped.
+// The whole name is kept if template has no measurement part. Extra
+// `key=value` tags are appended to every matched metric. Metrics with
+// tags are already in the tagged model and are never templated.
+use smallvec::SmallVec;
+use std::borrow::Cow;
+
+enum Part {
+    Skip,
+    Measurement,
+    MeasurementRest,
+    Tag(String),
+}
+
+pub struct Template {
+    pub pattern: String,
+    filter: Vec<String>,
+    parts: Vec<Part>,
+    tags: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+pub struct Templates {
+    templates: Vec<Template>,
+}
+
+impl Template {
+    pub fn new(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        // template is the only field which isn't a filter or key=value list
+        let (filter, template, tags) = match fields.as_slice() {
+            [template] => (None, *template, None),
+            [a, b] if b.contains('=') => (None, *a, Some(*b)),
+            [filter, template] => (Some(*filter), *template, None),
+            [filter, template, tags] => (Some(*filter), *template, Some(*tags)),
+            _ => return Err(format!("invalid template {:?}", line)),
+        };
+
+        let parts = template
+            .split('.')
+            .map(|part| match part {
+                "" => Part::Skip,
+                "measurement" => Part::Measurement,
+                "measurement*" => Part::MeasurementRest,
+                tag => Part::Tag(tag.to_string()),
+            })
+            .collect();
+
+        let tags = tags
+            .map(|tags| {
+                tags.split(',')
+                    .map(|tag| {
+                        tag.split_once('=')
+                            .map(|(k, v)| (k.to_string(), v.to_string()))
+                            .ok_or_else(|| format!("invalid template tag {:?}", tag))
+                    })
+                    .collect::<Result<Vec<_>, String>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Self {
+            pattern: line.to_string(),
+            filter: filter
+                .map(|f| f.split('.').map(String::from).collect())
+                .unwrap_or_default(),
+            parts,
+            tags,
+        })
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        let mut segments = name.split('.');
+        self.filter
+            .iter()
+            .all(|f| segments.next().is_some_and(|s| f == "*" || f == s))
+    }
+
+    fn apply<'a>(
+        &'a self,
+        name: &'a str,
+        tags: &mut SmallVec<[(&'a str, &'a str); 16]>,
+    ) -> Cow<'a, str> {
+        let mut measurement: Vec<&'a str> = Vec::new();
+        let mut rest = name;
+
+        for part in &self.parts {
+            if rest.is_empty() {
+                break;
+            }
+            let (segment, tail) = rest.split_once('.').unwrap_or((rest, ""));
+
+            match part {
+                Part::Skip => {}
+                Part::Measurement => measurement.push(segment),
+                Part::MeasurementRest => {
+                    measurement.push(rest);
+                    break;
+                }
+                Part::Tag(key) => tags.push((key, segment)),
+            }
+            rest = tail;
+        }
+
+        for (key, value) in &self.tags {
+            tags.push((key, value));
+        }
+
+        match measurement.as_slice() {
+            [] => Cow::Borrowed(name),
+            [single] => Cow::Borrowed(single),
+            parts => Cow::Owned(parts.join(".")),
+        }
+    }
+}
+
+impl Templates {
+    // parse templates, empty lines and `#` comments are skipped
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let templates = content
+            .lines()
+            .enumerate()
+            .map(|(n, line)| (n, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(n, line)| Template::new(line).map_err(|e| format!("line {}: {}", n + 1, e)))
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { templates })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read templates {}: {}", path, e))?;
+        Self::parse(&content)
+    }
+
+    pub fn templates(&self) -> &[Template] {
+        &self.templates
+    }
+
+    // extract tags from untagged name, returns the new metric name
+    pub fn apply<'a>(
+        &'a self,
+        name: &'a str,
+        tags: &mut SmallVec<[(&'a str, &'a str); 16]>,
+    ) -> Cow<'a, str> {
+        if !tags.is_empty() {
+            return Cow::Borrowed(name);
+        }
+
+        match self.templates.iter().find(|t| t.matches(name)) {
+            Some(template) => template.apply(name, tags),
+            None => Cow::Borrowed(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
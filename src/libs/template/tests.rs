@@ -0,0 +1,61 @@
+use super::*;
+
+const TEMPLATES: &str = "
+# legacy servers metrics
+servers.* .dc.host.measurement*
+*.app env.service.resource.measurement region=eu,team=core
+measurement.host.measurement
+";
+
+fn apply<'a>(templates: &'a Templates, name: &'a str) -> (String, Vec<(&'a str, &'a str)>) {
+    let mut tags = SmallVec::new();
+    let name = templates.apply(name, &mut tags).into_owned();
+    (name, tags.into_vec())
+}
+
+#[test]
+fn test_template_parse() {
+    let templates = Templates::parse(TEMPLATES).unwrap();
+
+    assert_eq!(templates.templates().len(), 3);
+    assert!(Templates::parse("a b c d").is_err());
+    assert!(Templates::parse("servers.* host.measurement region").is_err());
+}
+
+#[test]
+fn test_template_apply() {
+    let templates = Templates::parse(TEMPLATES).unwrap();
+
+    let (name, tags) = apply(&templates, "servers.dc1.web01.cpu.user");
+    assert_eq!(name, "cpu.user");
+    assert_eq!(tags, vec![("dc", "dc1"), ("host", "web01")]);
+
+    let (name, tags) = apply(&templates, "prod.app.billing.requests");
+    assert_eq!(name, "requests");
+    assert_eq!(
+        tags,
+        vec![
+            ("env", "prod"),
+            ("service", "app"),
+            ("resource", "billing"),
+            ("region", "eu"),
+            ("team", "core")
+        ]
+    );
+
+    let (name, tags) = apply(&templates, "cpu.web01.idle");
+    assert_eq!(name, "cpu.idle");
+    assert_eq!(tags, vec![("host", "web01")]);
+}
+
+#[test]
+fn test_template_skip_tagged() {
+    let templates = Templates::parse(TEMPLATES).unwrap();
+    let mut tags: SmallVec<[(&str, &str); 16]> = SmallVec::new();
+    tags.push(("host", "web01"));
+
+    let name = templates.apply("servers.dc1.web01.cpu", &mut tags);
+
+    assert_eq!(name, "servers.dc1.web01.cpu");
+    assert_eq!(tags.len(), 1);
+}
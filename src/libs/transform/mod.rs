@@ -0,0 +1,179 @@
+// Value and timestamp transforms for sensitive series, one rule per line:
+//
+//     <selector> <transform> [args...]
+//
+//     glob:billing.revenue.* round 2
+//     ^app\.users;.*tier=gold scale 1.37
+//     glob:*.latency;env=prod noise 0.5 10
+//     glob:jobs.* truncate 60
+//
+// Selector is an allow/block list rule (regex or `glob:`), see filter
+// module. Every matching rule is applied in file order:
+//
+// - `round N`: round value to N significant digits
+// - `scale F`: multiply value by a secret factor F
+// - `noise B [MAX]`: add Laplace(0, B) noise, bounded by MAX if provided
+// - `truncate S`: truncate timestamp to the start of S seconds bucket
+// - `shift S`: shift timestamp by a secret offset of S seconds
+//
+// Rules file contains secret factors and offsets and has to be protected
+// accordingly.
+use crate::libs::filter;
+use crate::libs::graphite::GraphiteMetric;
+use rand::Rng;
+use rand::distr::Open01;
+
+enum Op {
+    Round(i32),
+    Scale(f64),
+    Noise { scale: f64, bound: Option<f64> },
+    Truncate(i64),
+    Shift(i64),
+}
+
+pub struct Rule {
+    // selector and transform name, arguments are secret and left out
+    pub pattern: String,
+    selector: filter::Rule,
+    op: Op,
+}
+
+#[derive(Default)]
+pub struct Transforms {
+    rules: Vec<Rule>,
+}
+
+// round to `digits` significant digits, zero, subnormal and non-finite
+// values are kept, as well as values the factor overflows for
+fn round(value: f64, digits: i32) -> f64 {
+    if !value.is_normal() {
+        return value;
+    }
+
+    let magnitude = value.abs().log10().floor() as i32 + 1;
+    let factor = 10f64.powi(digits - magnitude);
+    let rounded = (value * factor).round() / factor;
+    if rounded.is_finite() { rounded } else { value }
+}
+
+// Laplace(0, scale) quantile of `u` in the open (-0.5, 0.5) interval
+fn laplace_at(scale: f64, u: f64) -> f64 {
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
+
+// sample Laplace(0, scale) noise, `u` never hits -0.5 where ln(0) is inf
+fn laplace(scale: f64) -> f64 {
+    let u: f64 = rand::rng().sample(Open01);
+    laplace_at(scale, u - 0.5)
+}
+
+fn arg<T: std::str::FromStr>(args: &[&str], i: usize, line: &str) -> Result<T, String> {
+    args.get(i)
+        .ok_or_else(|| format!("missing argument in {:?}", line))?
+        .parse()
+        .map_err(|_| format!("invalid argument {:?} in {:?}", args[i], line))
+}
+
+// finite positive factor, bound or noise scale
+fn positive(args: &[&str], i: usize, line: &str) -> Result<f64, String> {
+    match arg::<f64>(args, i, line)? {
+        value if value.is_finite() && value > 0.0 => Ok(value),
+        _ => Err(format!("{:?} has to be positive in {:?}", args[i], line)),
+    }
+}
+
+impl Rule {
+    pub fn new(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [selector, op, args @ ..] = fields.as_slice() else {
+            return Err(format!("expected `selector transform [args]`: {:?}", line));
+        };
+
+        let transform = match *op {
+            "round" => match arg(args, 0, line)? {
+                digits if digits >= 1 => Op::Round(digits),
+                _ => return Err(format!("digits have to be positive in {:?}", line)),
+            },
+            "scale" => Op::Scale(positive(args, 0, line)?),
+            "noise" => Op::Noise {
+                scale: positive(args, 0, line)?,
+                bound: args.get(1).map(|_| positive(args, 1, line)).transpose()?,
+            },
+            "truncate" => match arg(args, 0, line)? {
+                bucket if bucket > 0 => Op::Truncate(bucket),
+                _ => return Err(format!("bucket has to be positive in {:?}", line)),
+            },
+            "shift" => Op::Shift(arg(args, 0, line)?),
+            other => return Err(format!("unknown transform {:?}", other)),
+        };
+
+        Ok(Self {
+            pattern: format!("{} {}", selector, op),
+            selector: filter::Rule::new(selector)?,
+            op: transform,
+        })
+    }
+
+    fn apply(&self, metric: &mut GraphiteMetric) {
+        match self.op {
+            Op::Round(digits) => metric.value = round(metric.value, digits),
+            Op::Scale(factor) => metric.value *= factor,
+            Op::Noise { scale, bound } => {
+                let noise = laplace(scale);
+                metric.value += bound.map_or(noise, |b| noise.clamp(-b, b));
+            }
+            Op::Truncate(bucket) => {
+                let rem = metric.timestamp.rem_euclid(bucket);
+                metric.timestamp = metric.timestamp.saturating_sub(rem);
+            }
+            Op::Shift(offset) => metric.timestamp = metric.timestamp.saturating_add(offset),
+        }
+    }
+}
+
+impl Transforms {
+    // parse rules, empty lines and `#` comments are skipped
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let rules = content
+            .lines()
+            .enumerate()
+            .map(|(n, line)| (n, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(n, line)| Rule::new(line).map_err(|e| format!("line {}: {}", n + 1, e)))
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read transforms {}: {}", path, e))?;
+        Self::parse(&content)
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    // transform metric value and timestamp, `matched` is called for every
+    // applied rule
+    pub fn apply<F>(&self, metric: &mut GraphiteMetric, mut matched: F)
+    where
+        F: FnMut(&Rule),
+    {
+        if self.rules.is_empty() {
+            return;
+        }
+
+        let path = filter::path(metric);
+        for rule in &self.rules {
+            if rule.selector.matches(metric, &path) {
+                rule.apply(metric);
+                matched(rule);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
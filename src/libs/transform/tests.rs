@@ -0,0 +1,149 @@
+use super::*;
+
+fn apply(transforms: &Transforms, line: &str) -> (f64, usize) {
+    let mut metric = GraphiteMetric::parse(line).unwrap();
+    let mut matched = 0;
+    transforms.apply(&mut metric, |_| matched += 1);
+    (metric.value, matched)
+}
+
+#[test]
+fn test_transform_round() {
+    assert_eq!(round(123456.0, 2), 120000.0);
+    assert_eq!(round(0.012345, 3), 0.0123);
+    assert_eq!(round(-987.0, 1), -1000.0);
+    assert_eq!(round(0.0, 2), 0.0);
+}
+
+#[test]
+fn test_transform_round_extremes() {
+    assert_eq!(round(1e-310, 2), 1e-310);
+    assert_eq!(round(-1e-320, 2), -1e-320);
+    assert_eq!(round(2.2250738585072014e-308, 17), 2.2250738585072014e-308);
+    assert_eq!(round(f64::MAX, 1), f64::MAX);
+    assert!((round(1.7e300, 1) / 2e300 - 1.0).abs() < 1e-12);
+    assert!(round(f64::NAN, 2).is_nan());
+}
+
+#[test]
+fn test_transform_laplace_finite() {
+    // closest to the interval ends `Open01` could produce
+    let edge = f64::EPSILON / 2.0;
+    assert!(laplace_at(1.0, -0.5 + edge).is_finite());
+    assert!(laplace_at(1.0, 0.5 - edge).is_finite());
+    assert_eq!(laplace_at(1.0, 0.0), 0.0);
+
+    for _ in 0..10000 {
+        assert!(laplace(1.0).is_finite());
+    }
+}
+
+#[test]
+fn test_transform_label_without_args() {
+    let transforms = Transforms::parse(
+        "glob:billing.* scale 1.37
+glob:jobs.* noise 0.5 10
+",
+    )
+    .unwrap();
+
+    let patterns: Vec<&str> = transforms
+        .rules()
+        .iter()
+        .map(|r| r.pattern.as_str())
+        .collect();
+    assert_eq!(patterns, vec!["glob:billing.* scale", "glob:jobs.* noise"]);
+}
+
+#[test]
+fn test_transform_rules() {
+    let transforms = Transforms::parse(
+        "# revenue\nglob:billing.* scale 2\nglob:billing.revenue round 2\n^app\\.users;.*tier=gold round 1\n",
+    )
+    .unwrap();
+
+    assert_eq!(apply(&transforms, "billing.revenue 12345 1"), (25000.0, 2));
+    assert_eq!(apply(&transforms, "billing.orders 7 1"), (14.0, 1));
+    assert_eq!(apply(&transforms, "app.users;tier=gold 42 1"), (40.0, 1));
+    assert_eq!(apply(&transforms, "app.users;tier=free 42 1"), (42.0, 0));
+}
+
+#[test]
+fn test_transform_noise_bounded() {
+    let transforms = Transforms::parse("glob:app.* noise 100 0.5").unwrap();
+
+    for _ in 0..1000 {
+        let (value, matched) = apply(&transforms, "app.users 10 1");
+        assert_eq!(matched, 1);
+        assert!((9.5..=10.5).contains(&value));
+    }
+}
+
+#[test]
+fn test_transform_timestamp() {
+    let transforms =
+        Transforms::parse("glob:jobs.* truncate 60\nglob:jobs.* shift -3600\n").unwrap();
+
+    let mut metric = GraphiteMetric::parse("jobs.backup 1 1700000123").unwrap();
+    transforms.apply(&mut metric, |_| {});
+    assert_eq!(metric.timestamp, 1700000100 - 3600);
+    assert_eq!(metric.value, 1.0);
+
+    let mut metric = GraphiteMetric::parse("app.users 1 1700000123").unwrap();
+    transforms.apply(&mut metric, |_| {});
+    assert_eq!(metric.timestamp, 1700000123);
+}
+
+#[test]
+fn test_transform_timestamp_extremes() {
+    let transforms = Transforms::parse("glob:low.* truncate 60\nglob:high.* shift 3600\n").unwrap();
+
+    let line = format!("low.jobs 1 {}", i64::MIN + 1);
+    let mut metric = GraphiteMetric::parse(&line).unwrap();
+    transforms.apply(&mut metric, |_| {});
+    assert_eq!(metric.timestamp, i64::MIN);
+
+    let line = format!("high.jobs 1 {}", i64::MAX - 1);
+    let mut metric = GraphiteMetric::parse(&line).unwrap();
+    transforms.apply(&mut metric, |_| {});
+    assert_eq!(metric.timestamp, i64::MAX);
+}
+
+#[test]
+fn test_transform_shift_label_without_offset() {
+    let transforms = Transforms::parse("glob:tenant.acme.* shift -86413").unwrap();
+
+    assert_eq!(transforms.rules()[0].pattern, "glob:tenant.acme.* shift");
+}
+
+#[test]
+fn test_transform_invalid() {
+    assert!(Transforms::parse("glob:*").is_err());
+    assert!(Transforms::parse("glob:* round").is_err());
+    assert!(Transforms::parse("glob:* round x").is_err());
+    assert!(Transforms::parse("glob:* round 0").is_err());
+    assert!(Transforms::parse("glob:* round -2").is_err());
+    for args in ["scale -1", "scale 0", "scale nan", "scale inf"] {
+        assert!(
+            Transforms::parse(&format!("glob:* {}", args)).is_err(),
+            "{}",
+            args
+        );
+    }
+    for args in [
+        "noise -1",
+        "noise nan",
+        "noise 1 -5",
+        "noise 1 nan",
+        "noise 1 0",
+    ] {
+        assert!(
+            Transforms::parse(&format!("glob:* {}", args)).is_err(),
+            "{}",
+            args
+        );
+    }
+    assert!(Transforms::parse("glob:* shuffle").is_err());
+    assert!(Transforms::parse("glob:* truncate 0").is_err());
+    assert!(Transforms::parse("glob:* shift 1.5").is_err());
+}
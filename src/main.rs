@@ -1,19 +1,42 @@
-mod libs;
+mod cli;
 
-use libs::ch::{ClickHouseWriter, Metric};
-use libs::config::{self, PrometheusLabels};
-use libs::graphite;
-use libs::obf;
-use libs::prometheus::Prometheus;
-use libs::server;
+use sleipnir::libs::cache::{Cache, Outcome};
+use sleipnir::libs::ch::{ClickHouseWriter, Mapping, Metric, Schema, SchemaError};
+use sleipnir::libs::collision::Detector;
+use sleipnir::libs::config::{self, PrometheusLabels};
+use sleipnir::libs::filter::{self, Filter};
+use sleipnir::libs::graphite;
+use sleipnir::libs::kanon::Anonymizer;
+use sleipnir::libs::mapping::Seen;
+use sleipnir::libs::obf::{self, Obfuscator, Routes};
+use sleipnir::libs::pii;
+use sleipnir::libs::prometheus::Prometheus;
+use sleipnir::libs::rewrite::Rewriter;
+use sleipnir::libs::server;
+use sleipnir::libs::template::Templates;
+use sleipnir::libs::transform::Transforms;
 
-use axum::{Router, routing::get};
-use std::sync::Arc;
+use axum::{Router, http::StatusCode, routing::get};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// blocked by leak guard warning is logged once per interval per worker
+const LEAK_WARN_INTERVAL: Duration = Duration::from_secs(60);
 
 #[tokio::main]
 async fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
+    // offline tools
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("rewrite") => std::process::exit(cli::rewrite(&args[2..])),
+        Some("obfuscate") => std::process::exit(cli::obfuscate(&args[2..])),
+        Some("decrypt") => std::process::exit(cli::decrypt(&args[2..])),
+        _ => {}
+    }
+
     let config = config::load();
     log::debug!("configuration: {:?}", config);
 
@@ -38,13 +61,326 @@ async fn main() {
 
     let promc_main = promc.clone();
 
+    // init rewrite rules
+    let rewriter = match &config.rewrite_rules {
+        Some(path) => Rewriter::load(path).unwrap_or_else(|e| {
+            log::error!("unable to load rewrite rules: {}", e);
+            std::process::exit(1);
+        }),
+        None => Rewriter::default(),
+    };
+    log::info!("loaded {} rewrite rules", rewriter.rules().len());
+    let rewriter = Arc::new(rewriter);
+
+    // init allow and block lists
+    let filter = Filter::load(config.allow_list.as_deref(), config.block_list.as_deref())
+        .unwrap_or_else(|e| {
+            log::error!("unable to load filter rules: {}", e);
+            std::process::exit(1);
+        });
+    log::info!("loaded {} filter rules", filter.len());
+    let filter = Arc::new(filter);
+
+    // init graphite templates
+    let templates = match &config.templates {
+        Some(path) => Templates::load(path).unwrap_or_else(|e| {
+            log::error!("unable to load templates: {}", e);
+            std::process::exit(1);
+        }),
+        None => Templates::default(),
+    };
+    log::info!("loaded {} templates", templates.templates().len());
+    let templates = Arc::new(templates);
+
+    // init value transforms
+    let transforms = match &config.transforms {
+        Some(path) => Transforms::load(path).unwrap_or_else(|e| {
+            log::error!("unable to load transforms: {}", e);
+            std::process::exit(1);
+        }),
+        None => Transforms::default(),
+    };
+    log::info!("loaded {} transforms", transforms.rules().len());
+    let transforms = Arc::new(transforms);
+
+    // init obfuscation
+    let obf_config = config::load_obf();
+    let options = obf::Options::load(&obf_config).unwrap_or_else(|e| {
+        log::error!("unable to init obfuscation: {}", e);
+        std::process::exit(1);
+    });
+    if let obf::Strategy::Legacy = options.strategy {
+        log::warn!("no obfuscation key configured, using legacy unkeyed hashing");
+    }
+    log::info!("obfuscation token prefix: {}", options.strategy.prefix());
+    if let Some(rotation) = &options.rotation {
+        log::info!(
+            "obfuscation key rotation: writing {} tokens too until {}",
+            rotation.strategy.prefix(),
+            rotation.until
+        );
+    }
+
+    // init personal data detector, matches are hashed with the current key
+    // and with the previous one for previous key paths
+    let pii_action = config.pii_action.parse().unwrap_or_else(|e| {
+        log::error!("unable to init pii detector: {}", e);
+        std::process::exit(1);
+    });
+    let mut detector = pii::Detector::new(pii_action, options.strategy.clone());
+    if let Some(rotation) = &options.rotation {
+        detector.previous(rotation.strategy.clone());
+    }
+    let pii_loaded = config
+        .pii_detect
+        .iter()
+        .try_for_each(|name| detector.builtin(name))
+        .and_then(|_| match &config.pii_patterns {
+            Some(path) => detector.load(path),
+            None => Ok(()),
+        });
+    if let Err(e) = pii_loaded {
+        log::error!("unable to init pii detector: {}", e);
+        std::process::exit(1);
+    }
+    log::info!("loaded {} pii patterns", detector.patterns().len());
+    let detector = Arc::new(detector);
+
+    // init obfuscators per routing rule, for the previous key too
+    let load_routes = |options: &obf::Options| match &obf_config.obf_routes {
+        Some(path) => Routes::load(path, options).unwrap_or_else(|e| {
+            log::error!("unable to load obfuscation routes: {}", e);
+            std::process::exit(1);
+        }),
+        None => Routes::new(Box::new(options.clone())),
+    };
+    let obfuscator = Arc::new(load_routes(&options));
+    let previous = options.previous().map(|p| Arc::new(load_routes(&p)));
+    log::info!("loaded {} obfuscation routes", obfuscator.routes().len());
+
+    // init k-anonymity bucketing of rare tag values
+    let anonymizer = (config.kanon_k > 0).then(|| {
+        Arc::new(Anonymizer::new(
+            config.kanon_k as usize,
+            config.kanon_window,
+            config.kanon_keys.clone(),
+            config.kanon_size as usize,
+        ))
+    });
+
+    // init output leak guard, tokens of both keys and of routed strategies
+    // are allowed during rotation
+    let guard = config.leak_guard.then(|| {
+        let strategies = std::iter::once(&options.strategy)
+            .chain(options.strategies.iter())
+            .chain(
+                options
+                    .rotation
+                    .iter()
+                    .flat_map(|r| std::iter::once(&r.strategy).chain(r.strategies.iter())),
+            )
+            .cloned()
+            .collect();
+        let guard = obf::Guard::new(
+            strategies,
+            config.guard_segments.as_deref(),
+            config.guard_tag_keys.as_deref(),
+            config.guard_tag_values.as_deref(),
+        );
+        Arc::new(guard.unwrap_or_else(|e| {
+            log::error!("unable to init leak guard: {}", e);
+            std::process::exit(1);
+        }))
+    });
+
+    // init obfuscated paths caches, one per key
+    let cache_size = config.obf_cache_size as usize;
+    let new_cache = || (cache_size > 0).then(|| Arc::new(Cache::new(cache_size)));
+    let cache = new_cache();
+    let prev_cache = previous.as_ref().and_then(|_| new_cache());
+
+    // init obfuscation collision detector, cleartext is kept for debug logs only
+    let collisions = (config.collision_check_size > 0).then(|| {
+        Arc::new(Detector::new(
+            config.collision_check_size as usize,
+            config.collision_sample.into(),
+            log::log_enabled!(log::Level::Debug),
+        ))
+    });
+
+    // metrics and mapping tables, with bootstrap schema and written columns
+    let defaults = Schema::metrics();
+    let schema = Schema {
+        engine: config.ch_engine.clone().unwrap_or(defaults.engine),
+        partition_by: match &config.ch_partition_by {
+            Some(partition_by) if partition_by.is_empty() => None,
+            Some(partition_by) => Some(partition_by.clone()),
+            None => defaults.partition_by,
+        },
+        order_by: config.ch_order_by.clone().unwrap_or(defaults.order_by),
+        ttl: match &config.ch_ttl {
+            Some(ttl) if ttl.is_empty() => None,
+            Some(ttl) => Some(ttl.clone()),
+            None => defaults.ttl,
+        },
+        cluster: config.ch_cluster.clone(),
+        columns: defaults.columns,
+    };
+    let mut tables = vec![(
+        ClickHouseWriter::new(
+            &config.ch_url,
+            &config.ch_database,
+            &config.ch_username,
+            &config.ch_password,
+            &config.ch_table,
+        ),
+        schema,
+        Metric::COLUMNS,
+    )];
+    if let Some(url) = &config.map_ch_url {
+        let schema = Schema {
+            cluster: config.ch_cluster.clone(),
+            ..Schema::mapping()
+        };
+        tables.push((
+            ClickHouseWriter::new(
+                url,
+                &config.map_ch_database,
+                &config.map_ch_username,
+                &config.map_ch_password,
+                &config.map_ch_table,
+            ),
+            schema,
+            Mapping::COLUMNS,
+        ));
+    }
+    let tables = Arc::new(tables);
+
+    // create database and tables if they don't exist yet
+    if config.ch_bootstrap {
+        for (writer, schema, _) in tables.iter() {
+            if let Err(e) = writer.bootstrap(schema).await {
+                log::error!("unable to create table: {}", e);
+                std::process::exit(1);
+            }
+        }
+        log::info!("clickhouse tables are ready");
+    }
+
+    // refuse to start when tables don't match written rows, tables which
+    // can't be described are left to readiness checks
+    if config.ch_validate {
+        let mut valid = true;
+        for (writer, _, columns) in tables.iter() {
+            match writer.validate(columns).await {
+                Ok(()) => {}
+                Err(e @ SchemaError::Mismatch { .. }) => {
+                    log::error!("{}", e);
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    log::warn!("{}", e);
+                    valid = false;
+                }
+            }
+        }
+        if valid {
+            log::info!("clickhouse schema is valid");
+        }
+    }
+
+    // init obfuscation mapping table writer
+    let mapping = config.map_ch_url.as_ref().map(|url| {
+        let (map_tx, map_rx) = flume::bounded::<Mapping>(config.channel_buffer.try_into().unwrap());
+        let writer = ClickHouseWriter::new(
+            url,
+            &config.map_ch_database,
+            &config.map_ch_username,
+            &config.map_ch_password,
+            &config.map_ch_table,
+        );
+        let batch_size = config.batch_size;
+        let flush_interval = config.flush_interval;
+        let promc = promc.clone();
+
+        tokio::spawn(async move {
+            let mut inserter =
+                writer.create_inserter::<Mapping>(batch_size.into(), flush_interval.into());
+            log::info!("mapping writer started");
+
+            loop {
+                // wake up periodically, so idle inserter is flushed too
+                let period = Duration::from_secs(flush_interval.into());
+                match tokio::time::timeout(period, map_rx.recv_async()).await {
+                    Ok(Ok(mapping)) => {
+                        if let Err(e) = inserter.write(&mapping).await {
+                            log::error!("mapping: failed to write: {}", e);
+                            promc.errors.get_or_create(&promc.labels).inc();
+                        }
+                    }
+                    Ok(Err(_)) => break,
+                    Err(_) => {}
+                }
+
+                if let Err(e) = inserter.commit().await {
+                    log::error!("mapping: inserter: unable to commit: {}", e);
+                    promc.errors.get_or_create(&promc.labels).inc();
+                }
+            }
+
+            if let Err(e) = inserter.end().await {
+                log::error!("mapping: inserter: unable to end: {}", e);
+            }
+        });
+
+        (Arc::new(Seen::new(config.map_seen_size as usize)), map_tx)
+    });
+
     // init exporter (web)
     let promc_web = promc_main.clone();
     let prometheus_host = config.prometheus_host.clone();
     let prometheus_port = config.prometheus_port;
+    let validate = config.ch_validate;
+    let validate_interval = Duration::from_secs(config.ch_validate_interval);
 
     tokio::spawn(async move {
-        let app = Router::new().route("/metrics", get(|| async move { promc_web.export() }));
+        // ready while tables match written rows, the last check is reused
+        // for `ch_validate_interval` seconds, so probes don't query clickhouse
+        let checked = Arc::new(Mutex::new(None::<(Instant, Result<(), String>)>));
+        let ready = move || {
+            let (tables, checked) = (tables.clone(), checked.clone());
+            async move {
+                let cached = checked
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .filter(|(at, _)| at.elapsed() < validate_interval)
+                    .map(|(_, result)| result.clone());
+                let result = match cached {
+                    Some(result) => result,
+                    None => {
+                        let mut result = Ok(());
+                        for (writer, _, columns) in tables.iter().filter(|_| validate) {
+                            result = writer.validate(columns).await.map_err(|e| e.to_string());
+                            if result.is_err() {
+                                break;
+                            }
+                        }
+                        *checked.lock().unwrap() = Some((Instant::now(), result.clone()));
+                        result
+                    }
+                };
+
+                match result {
+                    Ok(()) => (StatusCode::OK, "ready".to_string()),
+                    Err(e) => (StatusCode::SERVICE_UNAVAILABLE, e),
+                }
+            }
+        };
+
+        let app = Router::new()
+            .route("/metrics", get(|| async move { promc_web.export() }))
+            .route("/ready", get(ready));
 
         let listener =
             tokio::net::TcpListener::bind(format!("{}:{}", prometheus_host, prometheus_port))
@@ -69,8 +405,28 @@ async fn main() {
         let ch_password = config.ch_password.clone();
         let ch_table = config.ch_table.clone();
 
+        let options = options.clone();
+        let obfuscator = obfuscator.clone();
+        let previous = previous.clone();
+        let cache = cache.clone();
+        let prev_cache = prev_cache.clone();
+        let collisions = collisions.clone();
+        let guard = guard.clone();
+        let mapping = mapping.clone();
+
         let promc = promc.clone();
         let labels = promc.worker_id(worker_id.into());
+        let key_labels = labels.key(options.strategy.version());
+        let prev_labels = options
+            .rotation
+            .as_ref()
+            .map(|r| labels.key(r.strategy.version()));
+        let rewriter = rewriter.clone();
+        let filter = filter.clone();
+        let templates = templates.clone();
+        let transforms = transforms.clone();
+        let detector = detector.clone();
+        let anonymizer = anonymizer.clone();
 
         tokio::spawn(async move {
             log::info!("worker {} started", worker_id);
@@ -79,11 +435,45 @@ async fn main() {
                 ClickHouseWriter::new(&ch_url, &ch_database, &ch_username, &ch_password, &ch_table);
             log::info!("[{}]: created writer", worker_id);
 
-            let mut inserter = writer.create_inserter(batch_size.into(), flush_interval.into());
+            let mut inserter =
+                writer.create_inserter::<Metric>(batch_size.into(), flush_interval.into());
             log::info!("[{}]: created inserter", worker_id);
 
             let mut processed: u64 = 0;
 
+            // the last check before write, leaked cleartext is logged at debug only
+            // blocked points are counted, warnings are rate limited
+            let warned = Mutex::new(None::<Instant>);
+            let leaks = |path: &str| {
+                let Some(Err(leak)) = guard.as_ref().map(|g| g.check(path)) else {
+                    return false;
+                };
+                let mut warned = warned.lock().unwrap();
+                if warned.is_none_or(|at| at.elapsed() >= LEAK_WARN_INTERVAL) {
+                    log::warn!(
+                        "[{}]: metric blocked: cleartext {}, see leaked counter",
+                        worker_id,
+                        leak.kind()
+                    );
+                    *warned = Some(Instant::now());
+                }
+                log::debug!("[{}]: blocked {}: {}", worker_id, path, leak);
+                promc.leaked.get_or_create(&labels).inc();
+                true
+            };
+
+            let count_cache = |outcome| {
+                let counter = match outcome {
+                    Outcome::Hit => &promc.cache_hits,
+                    Outcome::Miss => &promc.cache_misses,
+                    Outcome::Evicted => {
+                        promc.cache_evictions.get_or_create(&labels).inc();
+                        &promc.cache_misses
+                    }
+                };
+                counter.get_or_create(&labels).inc();
+            };
+
             loop {
                 match rx.recv_async().await {
                     Ok(msg) => {
@@ -94,10 +484,12 @@ async fn main() {
                             1 // return (set) 1 and start again
                         });
 
+                        #[allow(clippy::manual_is_multiple_of)]
                         if processed % 10000_u64 == 0 {
                             log::info!("[{}]: processed {} metrics", worker_id, processed);
                         }
 
+                        #[allow(clippy::manual_is_multiple_of)]
                         if processed % batch_size as u64 == 0 {
                             match inserter.commit().await {
                                 Ok(_) => {
@@ -117,12 +509,134 @@ async fn main() {
                             }
                         }
 
+                        // rewritten names have to outlive the parsed metric
+                        let (name, measurement, redacted);
+
                         match graphite::GraphiteMetric::parse(&msg) {
-                            Ok(metric) => {
-                                let mut buf = [0u8; obf::MAX_METRIC_LEN];
-                                let obf_path = obf::obfuscate(&metric, &mut buf);
+                            Ok(mut metric) => {
+                                if let Err(rule) = filter.check(&metric) {
+                                    log::debug!("[{}]: filtered by {}: {}", worker_id, rule, msg);
+                                    promc.filtered.get_or_create(&labels.rule(rule)).inc();
+                                    continue;
+                                }
+
+                                name = rewriter.apply(metric.name, |rule| {
+                                    promc
+                                        .rewritten
+                                        .get_or_create(&labels.rule(&rule.pattern))
+                                        .inc();
+                                });
+                                metric.name = &name;
+
+                                measurement = templates.apply(metric.name, &mut metric.tags);
+                                metric.name = &measurement;
+
+                                transforms.apply(&mut metric, |rule| {
+                                    promc
+                                        .transformed
+                                        .get_or_create(&labels.rule(&rule.pattern))
+                                        .inc();
+                                });
+
+                                redacted = detector.scan(&metric, |pattern| {
+                                    promc
+                                        .pii_detected
+                                        .get_or_create(&labels.rule(&pattern.name))
+                                        .inc();
+                                });
+                                redacted.apply(&mut metric);
+
+                                let now = SystemTime::now()
+                                    .duration_since(UNIX_EPOCH)
+                                    .map_or(0, |d| d.as_secs() as i64);
+
+                                if let Some(anonymizer) = &anonymizer {
+                                    anonymizer.apply(&mut metric, now, |entry| {
+                                        promc.rare.get_or_create(&labels.rule(entry)).inc();
+                                    });
+                                }
+
+                                // check collisions, queue mappings of newly seen tokens,
+                                // paths with dropped mappings aren't kept in cache
+                                let dropped = AtomicBool::new(false);
+                                let mut on_token = |clear: &str, token: &str| {
+                                    if let Some(detector) = &collisions
+                                        && let Err(collision) = detector.check(clear, token)
+                                    {
+                                        log::warn!(
+                                            "[{}]: obfuscation collision: {}",
+                                            worker_id,
+                                            token
+                                        );
+                                        log::debug!(
+                                            "[{}]: collision of {}: {:?} and {:?}",
+                                            worker_id,
+                                            token,
+                                            collision.previous,
+                                            clear
+                                        );
+                                        promc.collisions.get_or_create(&labels).inc();
+                                    }
+
+                                    let Some((seen, map_tx)) = &mapping else {
+                                        return;
+                                    };
+                                    if !seen.insert(token) {
+                                        return;
+                                    }
+
+                                    let row = Mapping {
+                                        token: token.to_string(),
+                                        cleartext: clear.to_string(),
+                                        timestamp: metric.timestamp,
+                                    };
+                                    match map_tx.try_send(row) {
+                                        Ok(_) => promc.mapped.get_or_create(&labels).inc(),
+                                        Err(e) => {
+                                            // fires per token under backpressure, see `mapping_dropped`
+                                            log::debug!(
+                                                "[{}]: mapping channel full, dropping: {}",
+                                                worker_id,
+                                                e
+                                            );
+                                            seen.remove(token);
+                                            dropped.store(true, Ordering::Relaxed);
+                                            promc.mapping_dropped.get_or_create(&labels).inc()
+                                        }
+                                    };
+                                };
+
+                                // cleartext path is the key of hot series caches
+                                let key = cache.as_ref().map(|c| (c, filter::path(&metric)));
+                                let obfuscated = match &key {
+                                    Some((cache, path)) => cache
+                                        .get_or_insert_with(path, || {
+                                            obfuscator.obfuscate_with(&metric, &mut on_token)
+                                        })
+                                        .map(|(path, outcome)| {
+                                            count_cache(outcome);
+                                            path
+                                        }),
+                                    None => obfuscator.obfuscate_with(&metric, &mut on_token),
+                                };
+                                if dropped.swap(false, Ordering::Relaxed)
+                                    && let Some((cache, path)) = &key
+                                {
+                                    cache.remove(path);
+                                }
+                                let obf_path = match obfuscated {
+                                    Ok(path) => path,
+                                    Err(e) => {
+                                        log::warn!("[{}]: metric rejected: {}", worker_id, e);
+                                        promc.rejected.get_or_create(&labels).inc();
+                                        continue;
+                                    }
+                                };
+                                if leaks(&obf_path) {
+                                    continue;
+                                }
                                 let obf_metric = Metric {
-                                    path: obf_path.to_string(),
+                                    path: obf_path,
                                     value: metric.value,
                                     timestamp: metric.timestamp,
                                 };
@@ -137,9 +651,74 @@ async fn main() {
 
                                 promc.processed.get_or_create(&labels).inc();
 
-                                if let Err(e) = inserter.write(&obf_metric).await {
-                                    log::error!("[{}]: failed to write metric: {}", worker_id, e);
-                                    promc.errors.get_or_create(&labels).inc();
+                                match inserter.write(&obf_metric).await {
+                                    Ok(_) => promc.obfuscated.get_or_create(&key_labels).inc(),
+                                    Err(e) => {
+                                        log::error!(
+                                            "[{}]: failed to write metric: {}",
+                                            worker_id,
+                                            e
+                                        );
+                                        promc.errors.get_or_create(&labels).inc()
+                                    }
+                                };
+
+                                // previous key representation during rotation
+                                if let (Some(previous), Some(prev_labels)) =
+                                    (&previous, &prev_labels)
+                                    && options.rotating(now)
+                                {
+                                    // personal data hashed with the previous key
+                                    let restored;
+                                    let source = match redacted.has_previous() {
+                                        true => {
+                                            let mut source = metric.clone();
+                                            redacted.apply_previous(&mut source);
+                                            restored = source;
+                                            &restored
+                                        }
+                                        false => &metric,
+                                    };
+
+                                    // limits are the same, so it never fails here, and
+                                    // cleartext routes are the same for both keys, old
+                                    // tokens are mapped and checked as well
+                                    let obfuscated = match (&prev_cache, &key) {
+                                        (Some(prev_cache), Some((_, path))) => prev_cache
+                                            .get_or_insert_with(path, || {
+                                                previous.obfuscate_with(source, &mut on_token)
+                                            })
+                                            .map(|(path, outcome)| {
+                                                count_cache(outcome);
+                                                path
+                                            }),
+                                        _ => previous.obfuscate_with(source, &mut on_token),
+                                    };
+                                    if dropped.swap(false, Ordering::Relaxed)
+                                        && let (Some(prev_cache), Some((_, path))) =
+                                            (&prev_cache, &key)
+                                    {
+                                        prev_cache.remove(path);
+                                    }
+                                    let Ok(path) = obfuscated else {
+                                        continue;
+                                    };
+                                    if path == obf_metric.path || leaks(&path) {
+                                        continue;
+                                    }
+                                    let prev_metric = Metric { path, ..obf_metric };
+
+                                    match inserter.write(&prev_metric).await {
+                                        Ok(_) => promc.obfuscated.get_or_create(prev_labels).inc(),
+                                        Err(e) => {
+                                            log::error!(
+                                                "[{}]: failed to write metric: {}",
+                                                worker_id,
+                                                e
+                                            );
+                                            promc.errors.get_or_create(&labels).inc()
+                                        }
+                                    };
                                 }
                             }
                             Err(e) => {
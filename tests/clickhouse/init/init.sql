@@ -17,3 +17,14 @@ ORDER BY (path, date, timestamp)
 
 -- retention
 TTL date + INTERVAL 90 DAY;
+
+-- obfuscation mapping, grant access to privileged users only
+CREATE TABLE IF NOT EXISTS sleipnir.mapping (
+    token String,
+    cleartext String,
+    timestamp Int64
+)
+
+-- one row per token after merges
+ENGINE = ReplacingMergeTree
+ORDER BY token;